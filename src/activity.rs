@@ -0,0 +1,185 @@
+//! Tells whether the athlete is jumping, so that sessions can be split into
+//! sets and trips counted.
+//!
+//! ```text
+//!         cadence for START_AFTER_MS
+//! Idle ---------------------------------> Jumping <----+
+//!                                          |  ^        | cadence again
+//!                           cadence lost   |  |        | within
+//!                                          v  |        | TRIP_AT_MOST_MS
+//! Resting <--- no cadence for ---------- Tripped ------+
+//!              TRIP_AT_MOST_MS
+//! ```
+//!
+//! From resting, the athlete starts jumping the same way as from idle.
+//!
+//! The cadence is only lost once the analyzers stop agreeing on it, which
+//! lags behind the athlete by up to the smallest window. Trips shorter than
+//! that go unnoticed.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Cadence must be present for this long before we consider the athlete to
+/// be jumping. Filters out short bursts of agreement in noise.
+pub const START_AFTER_MS: u64 = 1000;
+
+/// If the cadence is back within this time, the athlete has only tripped.
+/// Otherwise, they stopped jumping.
+pub const TRIP_AT_MOST_MS: u64 = 3000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Activity {
+    /// Not jumped yet.
+    Idle,
+    Jumping,
+    /// Stopped jumping a moment ago, might be a trip or the end of a set.
+    Tripped,
+    /// Stopped jumping after a set.
+    Resting,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityChange {
+    /// A set started.
+    Started,
+    /// The cadence was lost.
+    Tripped,
+    /// The cadence is back after a trip.
+    Recovered,
+    /// The set ended when the cadence was lost.
+    Stopped,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ActivityEvent {
+    /// When the change happened, on the video timeline. It can be in the past
+    /// when the change is only known later, e.g. a set stopped when the
+    /// athlete tripped.
+    pub at: Duration,
+    pub change: ActivityChange,
+}
+
+/// State machine fed with the cadence as time goes, see the module docs.
+#[derive(Debug, Clone)]
+pub struct ActivityDetector {
+    activity: Activity,
+    // Whether there was cadence in the latest update, and since when it's been
+    // like that.
+    streak: Option<(bool, Duration)>,
+    // Trips the athlete recovered from.
+    trips: usize,
+}
+
+impl ActivityDetector {
+    pub fn new() -> Self {
+        Self {
+            activity: Activity::Idle,
+            streak: None,
+            trips: 0,
+        }
+    }
+
+    pub fn activity(&self) -> Activity {
+        self.activity
+    }
+
+    pub fn trips(&self) -> usize {
+        self.trips
+    }
+
+    /// Feeds the current cadence, none if there isn't any. Returns the event
+    /// if the activity changed.
+    pub fn update(
+        &mut self,
+        at: Duration,
+        cadence: Option<f32>,
+    ) -> Option<ActivityEvent> {
+        let jumping = cadence.is_some();
+        let since = match self.streak {
+            Some((was_jumping, since)) if was_jumping == jumping => since,
+            _ => at,
+        };
+        self.streak = Some((jumping, since));
+        let streak = at.saturating_sub(since);
+
+        let (activity, change, at) = match (self.activity, jumping) {
+            (Activity::Idle, true) | (Activity::Resting, true)
+                if streak >= Duration::from_millis(START_AFTER_MS) =>
+            {
+                (Activity::Jumping, ActivityChange::Started, since)
+            }
+            (Activity::Jumping, false) => {
+                (Activity::Tripped, ActivityChange::Tripped, at)
+            }
+            (Activity::Tripped, true) => {
+                self.trips += 1;
+                (Activity::Jumping, ActivityChange::Recovered, at)
+            }
+            (Activity::Tripped, false)
+                if streak > Duration::from_millis(TRIP_AT_MOST_MS) =>
+            {
+                (Activity::Resting, ActivityChange::Stopped, since)
+            }
+            _ => return None,
+        };
+        self.activity = activity;
+
+        Some(ActivityEvent { at, change })
+    }
+}
+
+impl ActivityChange {
+    /// Same as the serialized name, e.g. "started".
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Started => "started",
+            Self::Tripped => "tripped",
+            Self::Recovered => "recovered",
+            Self::Stopped => "stopped",
+        }
+    }
+}
+
+impl Default for ActivityDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_detects_sets_and_trips() {
+        // jumps from 5s, trips at 10s for a second, stops at 20s
+        let mut detector = ActivityDetector::new();
+        let events: Vec<_> = (0..120)
+            .filter_map(|tick| {
+                let at = Duration::from_millis(tick * 250);
+                let cadence = match at.as_secs() {
+                    5..=9 | 11..=19 => Some(2.5),
+                    _ => None,
+                };
+                detector.update(at, cadence)
+            })
+            .map(|event| (event.at.as_secs_f32(), event.change))
+            .collect();
+
+        assert_eq!(
+            events,
+            vec![
+                (5.0, ActivityChange::Started),
+                (10.0, ActivityChange::Tripped),
+                (11.0, ActivityChange::Recovered),
+                (20.0, ActivityChange::Tripped),
+                (20.0, ActivityChange::Stopped),
+            ]
+        );
+        assert_eq!(detector.trips(), 1);
+        assert_eq!(detector.activity(), Activity::Resting);
+    }
+}
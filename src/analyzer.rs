@@ -1,12 +1,17 @@
+use crate::metrics::{AnalyzerMetrics, PipelineMetrics};
 use crate::oscillator::{Oscillator, WindowFn};
 use crate::prelude::*;
+use crate::preview::{Preview, Vote};
+use crate::source::Frame;
+use crate::spectrogram::{Histogram, Spectrogram};
 use image::GrayImage;
-use rand::{thread_rng, Rng};
+use rand::Rng;
 use rustfft::{num_complex::Complex, Fft, FftPlanner};
 use std::collections::HashMap;
 use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::Arc;
 use std::thread;
+use std::time::{Duration, Instant};
 
 /// This value is streamed from the spawned analyzer thread to update on what
 /// frequency has been identified.
@@ -14,7 +19,13 @@ use std::thread;
 pub struct Report {
     pub window: usize,
     pub frame_index: usize,
+    /// Timestamp of the frame which triggered this report.
+    pub timestamp: Duration,
     pub frequency: f32,
+    /// Share of the oscillators which agree on the frequency, out of those
+    /// which identified any frequency. It's always above a half, fewer
+    /// oscillators in agreement don't make a report.
+    pub confidence: f32,
 }
 
 pub struct AnalyzerBuilder {
@@ -25,42 +36,70 @@ pub struct AnalyzerBuilder {
     pub frame_height: u32,
 }
 
-/// Spawns a new thread based on the settings given. The returned sender updates
-/// the spawned analyzer thread on new frames. In consistent intervals, the
-/// thread updates the receiver on what frequency it thinks is most prevalent
-/// in the video.
-pub fn channel(
-    builder: AnalyzerBuilder,
-) -> (Sender<Arc<GrayImage>>, Receiver<Report>) {
-    let AnalyzerBuilder {
-        frame_rate,
-        window,
-        frame_width,
-        frame_height,
-    } = builder;
-
-    let mut rng = thread_rng();
-    let mut analyzer = Analyzer::new(frame_rate, window);
-
-    let oscillators_count = frame_width as usize * frame_height as usize / 25;
-    analyzer.init_oscillators(
-        &mut rng,
-        oscillators_count,
-        frame_width,
-        frame_height,
-    );
-
-    let (frame_sender, frame_recv) = mpsc::channel::<Arc<_>>();
-    let (frequency_sender, frequency_recv) = mpsc::channel();
+impl AnalyzerBuilder {
+    /// Creates an analyzer with oscillators randomly placed on the frame.
+    /// Fails if the frame rate is below [`MIN_FRAME_RATE`], or if the frame
+    /// is too small to place any oscillator on.
+    pub fn build(self, rng: &mut impl Rng) -> Result<Analyzer> {
+        let AnalyzerBuilder {
+            frame_rate,
+            window,
+            frame_width,
+            frame_height,
+        } = self;
+        if frame_rate < MIN_FRAME_RATE {
+            return Err(Error::FrameRateTooLow {
+                fps: frame_rate,
+                min: MIN_FRAME_RATE,
+            });
+        }
 
-    thread::spawn(move || {
-        let frames_per_ms = analyzer.frame_rate as f32 / 1000.0;
+        let oscillators_count =
+            frame_width as usize * frame_height as usize / 25;
+        // each oscillator sees a square of the frame
+        if frame_width <= VIEW_SIZE
+            || frame_height <= VIEW_SIZE
+            || oscillators_count == 0
+        {
+            return Err(Error::InvalidArgument(format!(
+                "Frames of {}x{} are too small to analyse",
+                frame_width, frame_height
+            )));
+        }
 
-        let update_frequency_every_nth_frame =
-            (REPORT_FREQUENCY_AFTER_MS as f32 * frames_per_ms) as usize;
-        let truncate_state_every_nth_frame =
-            (TRUNCATE_STATE_AFTER_MS as f32 * frames_per_ms) as usize;
+        let mut analyzer = Analyzer::new(frame_rate, window);
+        analyzer.init_oscillators(
+            rng,
+            oscillators_count,
+            frame_width,
+            frame_height,
+        );
+
+        Ok(analyzer)
+    }
+}
+
+/// Spawns a new thread which runs given analyzer of the source at given index
+/// in the pipeline, which labels the metrics it registers in the metrics of
+/// the pipeline. The returned sender updates
+/// the spawned analyzer thread on new frames. For each frame, the thread
+/// answers the receiver with the report the frame triggered, if any, see
+/// [`Analyzer::push_frame`].
+///
+/// The preview and the spectrogram are set up on the analyzer beforehand, see
+/// [`Analyzer::with_preview`] and [`Analyzer::with_spectrogram`].
+pub(crate) fn channel(
+    mut analyzer: Analyzer,
+    source: usize,
+    pipeline_metrics: &PipelineMetrics,
+) -> (Sender<Arc<Frame>>, Receiver<Option<Report>>) {
+    let metrics = pipeline_metrics.register_analyzer(source, analyzer.window);
+    analyzer.metrics = Some(Arc::clone(&metrics));
 
+    let (frame_sender, frame_recv) = mpsc::channel::<Arc<Frame>>();
+    let (frequency_sender, frequency_recv) = mpsc::channel();
+
+    thread::spawn(move || {
         // with these iterator we make a fundamental but justified assumption
         // that it on average takes longer time to deliver new messages than
         // to process them
@@ -70,28 +109,13 @@ pub fn channel(
         //
         // however most cameras have pretty low FPS and the computation we do
         // on average is super cheap
-        for (frame_index, frame) in frame_recv.iter().enumerate() {
-            // pushes pixel values to relevant oscillators
-            analyzer.push_pixel_values_to_oscillators(&frame);
-
-            if frame_index % update_frequency_every_nth_frame == 0 {
-                if let Some(f) = analyzer.frequency() {
-                    if frequency_sender
-                        .send(Report {
-                            frame_index,
-                            frequency: f,
-                            window,
-                        })
-                        .is_err()
-                    {
-                        // channel died, video ended
-                        return;
-                    }
-                }
-            }
+        for frame in frame_recv.iter() {
+            let report = analyzer.push_frame(&frame);
+            metrics.frames_processed.inc();
 
-            if frame_index % truncate_state_every_nth_frame == 0 {
-                analyzer.truncate_state();
+            if frequency_sender.send(report).is_err() {
+                // channel died, the pipeline was dropped
+                return;
             }
         }
     });
@@ -99,12 +123,13 @@ pub fn channel(
     (frame_sender, frequency_recv)
 }
 
-// Keeps bunch of oscillators that keep track of video state history and return
-// frequencies in that state (each oscillator sees [`VIEW_SIZE`] pixels).
-//
-// The [`Analyzer`] can then put together estimates from each oscillator and
-// average it to get the final frequency.
-struct Analyzer {
+/// Keeps bunch of oscillators that keep track of video state history and
+/// return frequencies in that state (each oscillator sees [`VIEW_SIZE`]
+/// pixels).
+///
+/// The [`Analyzer`] can then put together estimates from each oscillator and
+/// average it to get the final frequency.
+pub struct Analyzer {
     // Initiated object which can run FFT.
     fft: Arc<dyn Fft<f32>>,
     // Map of pixel indices to objects which track them.
@@ -117,6 +142,18 @@ struct Analyzer {
     window_fn: WindowFn,
     // Allocated buffers for the FFT algorithm. They contain opaque data.
     scratch_buffers: (Vec<Complex<f32>>, Vec<Complex<f32>>),
+    // How many frames have been pushed so far.
+    frame_index: usize,
+    // See [`REPORT_FREQUENCY_AFTER_MS`].
+    update_frequency_every_nth_frame: usize,
+    // See [`TRUNCATE_STATE_AFTER_MS`].
+    truncate_state_every_nth_frame: usize,
+    // Where to record how long the frequency calculation takes, if anywhere.
+    metrics: Option<Arc<AnalyzerMetrics>>,
+    // Where to show what each oscillator votes for, if anywhere.
+    preview: Option<Arc<Preview>>,
+    // Where to share how the votes spread over frequencies, if anywhere.
+    spectrogram: Option<Arc<Spectrogram>>,
 }
 
 impl Analyzer {
@@ -131,6 +168,12 @@ impl Analyzer {
             buf
         };
 
+        let frames_per_ms = frame_rate as f32 / 1000.0;
+        let update_frequency_every_nth_frame =
+            (REPORT_FREQUENCY_AFTER_MS as f32 * frames_per_ms) as usize;
+        let truncate_state_every_nth_frame =
+            (TRUNCATE_STATE_AFTER_MS as f32 * frames_per_ms) as usize;
+
         Self {
             fft,
             frame_rate,
@@ -138,7 +181,57 @@ impl Analyzer {
             window_fn,
             oscillators: HashMap::new(),
             scratch_buffers: (create_buf(), create_buf()),
+            frame_index: 0,
+            update_frequency_every_nth_frame,
+            truncate_state_every_nth_frame,
+            metrics: None,
+            preview: None,
+            spectrogram: None,
+        }
+    }
+
+    /// Shows the votes of the oscillators in the preview with each report.
+    pub fn with_preview(mut self, preview: Arc<Preview>) -> Self {
+        self.preview = Some(preview);
+        self
+    }
+
+    /// Shares the histogram of the votes with the spectrogram with each
+    /// report.
+    pub fn with_spectrogram(mut self, spectrogram: Arc<Spectrogram>) -> Self {
+        self.spectrogram = Some(spectrogram);
+        self
+    }
+
+    /// Updates the oscillators with the new frame. In consistent intervals
+    /// (see [`REPORT_FREQUENCY_AFTER_MS`]), returns a report on what frequency
+    /// is most prevalent in the video if there's consensus on any.
+    pub fn push_frame(&mut self, frame: &Frame) -> Option<Report> {
+        let frame_index = self.frame_index;
+        self.frame_index += 1;
+
+        // pushes pixel values to relevant oscillators
+        self.push_pixel_values_to_oscillators(&frame.image);
+
+        let report = if frame_index % self.update_frequency_every_nth_frame == 0
+        {
+            let frequency = self.frequency(frame.timestamp);
+            frequency.map(|(frequency, confidence)| Report {
+                frame_index,
+                timestamp: frame.timestamp,
+                frequency,
+                confidence,
+                window: self.window,
+            })
+        } else {
+            None
+        };
+
+        if frame_index % self.truncate_state_every_nth_frame == 0 {
+            self.truncate_state();
         }
+
+        report
     }
 
     // Creates `oscillators_count` randomly placed (on a frame) oscillators
@@ -173,7 +266,9 @@ impl Analyzer {
         }
     }
 
-    fn frequency(&mut self) -> Option<f32> {
+    // Returns the frequency which most oscillators agree on, and the ratio of
+    // oscillators which agree. The timestamp is of the latest frame.
+    fn frequency(&mut self, timestamp: Duration) -> Option<(f32, f32)> {
         // Allows us to focus on frequencies in which people usually jump (not
         // too slow, not too fast).
         //
@@ -190,12 +285,52 @@ impl Analyzer {
         let mut bins_count: Vec<usize> = vec![];
         bins_count.resize(self.window / 2, 0);
 
-        for oscillator in self.oscillators.values() {
-            if let Some(bin) =
-                oscillator.frequency_bin(relevant_bins.clone(), a, b)
-            {
+        // which bin each oscillator votes for, only kept for the preview
+        let mut votes = vec![];
+
+        let started_at = Instant::now();
+        for (position, oscillator) in &self.oscillators {
+            let bin = oscillator.frequency_bin(relevant_bins.clone(), a, b);
+            if let Some(bin) = bin {
                 bins_count[bin] += 1;
             }
+            if self.preview.is_some() {
+                votes.push((*position, bin));
+            }
+        }
+
+        if let Some(metrics) = &self.metrics {
+            let fft_seconds = started_at.elapsed().as_secs_f64();
+            metrics.fft_latest_seconds.set(fft_seconds);
+            metrics.fft_seconds_sum.add(fft_seconds);
+            metrics.fft_seconds_count.inc();
+            let oscillating = self
+                .oscillators
+                .values()
+                .filter(|o| o.is_oscillating())
+                .count();
+            metrics.oscillators_oscillating.set(oscillating as f64);
+        }
+
+        if let Some(preview) = &self.preview {
+            let votes = votes
+                .into_iter()
+                .map(|((x, y), bin)| Vote {
+                    x,
+                    y,
+                    hz: bin.map(|bin| self.bin_to_frequency(bin)),
+                })
+                .collect();
+            preview.set_votes(votes);
+        }
+
+        if let Some(spectrogram) = &self.spectrogram {
+            let histogram = Histogram {
+                timestamp,
+                bin_hz: self.frame_rate as f32 / self.window as f32,
+                counts: bins_count.clone(),
+            };
+            spectrogram.update(self.window, histogram);
         }
 
         // find the couple of adjacent frequencies which together have the
@@ -208,10 +343,10 @@ impl Analyzer {
         let largest_couple_oscillators_count =
             (largest_couple[0] + largest_couple[1]) as f32;
         let oscillator_count: usize = bins_count.iter().sum();
+        let agreement =
+            largest_couple_oscillators_count / oscillator_count as f32;
 
-        if largest_couple_oscillators_count / oscillator_count as f32
-            > MIN_OSCILLATORS_AGREEMENT_RATIO
-        {
+        if agreement > MIN_OSCILLATORS_AGREEMENT_RATIO {
             let f1 = self.bin_to_frequency(bin1);
             let f1_share =
                 largest_couple[0] as f32 / largest_couple_oscillators_count;
@@ -220,7 +355,7 @@ impl Analyzer {
             let f2_share =
                 largest_couple[1] as f32 / largest_couple_oscillators_count;
 
-            Some(f1 * f1_share + f2 * f2_share)
+            Some((f1 * f1_share + f2 * f2_share, agreement))
         } else {
             None
         }
@@ -240,3 +375,119 @@ impl Analyzer {
         (bin * self.frame_rate) as f32 / self.window as f32
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::CadenceScene;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn it_shows_votes_in_preview() {
+        let frame_rate = 30;
+        let preview = Arc::new(Preview::new());
+        let spectrogram = Arc::new(Spectrogram::new());
+        let mut analyzer = AnalyzerBuilder {
+            frame_rate,
+            window: frame_rate * 4,
+            frame_width: 80,
+            frame_height: 60,
+        }
+        .build(&mut StdRng::seed_from_u64(0))
+        .unwrap()
+        .with_preview(Arc::clone(&preview))
+        .with_spectrogram(Arc::clone(&spectrogram));
+
+        let frames = CadenceScene::constant(2.5)
+            .with_noise(10)
+            .into_source(80, 60, frame_rate)
+            .with_duration(Duration::from_secs(6));
+        for frame in frames {
+            let frame = Arc::new(frame);
+            analyzer.push_frame(&frame);
+            preview.set_frame(frame);
+        }
+
+        let (_, _, votes) = preview.latest().expect("No preview");
+        assert_eq!(votes.len(), analyzer.oscillators.len());
+        let on_cadence = votes
+            .iter()
+            .filter(|v| matches!(v.hz, Some(hz) if (hz - 2.5).abs() < 0.3))
+            .count();
+        // the background doesn't move, only the athlete does
+        assert!(on_cadence > 0, "{:?}", votes);
+        assert!(votes.iter().any(|v| v.hz.is_none()));
+
+        // the histogram counts the same votes
+        let histograms = spectrogram.latest();
+        let histogram = &histograms[&(frame_rate * 4)];
+        let voted = votes.iter().filter(|v| v.hz.is_some()).count();
+        assert_eq!(histogram.counts.iter().sum::<usize>(), voted);
+        assert_eq!(histogram.bin_hz, 0.25);
+    }
+
+    #[test]
+    fn it_rejects_low_frame_rate() {
+        let builder = AnalyzerBuilder {
+            frame_rate: MIN_FRAME_RATE - 1,
+            window: 12,
+            frame_width: 8,
+            frame_height: 8,
+        };
+        assert!(matches!(
+            builder.build(&mut StdRng::seed_from_u64(0)),
+            Err(Error::FrameRateTooLow { .. })
+        ));
+    }
+
+    #[test]
+    fn it_rejects_tiny_frames() {
+        for &(frame_width, frame_height) in &[(1, 40), (40, 2), (4, 4)] {
+            let builder = AnalyzerBuilder {
+                frame_rate: 30,
+                window: 120,
+                frame_width,
+                frame_height,
+            };
+            assert!(matches!(
+                builder.build(&mut StdRng::seed_from_u64(0)),
+                Err(Error::InvalidArgument(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn it_recovers_constant_cadence() {
+        let frame_rate = 30;
+        let window = frame_rate * 8;
+        let mut analyzer = AnalyzerBuilder {
+            frame_rate,
+            window,
+            frame_width: 160,
+            frame_height: 120,
+        }
+        .build(&mut StdRng::seed_from_u64(0))
+        .unwrap();
+
+        let frames = CadenceScene::constant(2.5)
+            .with_noise(10)
+            .into_source(160, 120, frame_rate)
+            .with_duration(Duration::from_secs(15));
+        let reports: Vec<_> =
+            frames.filter_map(|f| analyzer.push_frame(&f)).collect();
+
+        // no report until the window is full
+        let first = reports.first().expect("No frequency reported");
+        assert!(first.frame_index >= window);
+
+        // a single bin is frame rate / window wide
+        let bin_width = frame_rate as f32 / window as f32;
+        for report in &reports {
+            assert!(
+                (report.frequency - 2.5).abs() < bin_width,
+                "Reported {} Hz",
+                report.frequency
+            );
+        }
+    }
+}
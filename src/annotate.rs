@@ -0,0 +1,220 @@
+//! Renders the session into a video to share with the athlete.
+//!
+//! The frames are analysed offline the same way as in the evaluation, see
+//! [`Pipeline`], and the cadence, the jump count and a graph of the recent
+//! cadence are burned into them. The frames stay grayscale, as that's what the
+//! analysis reads. Optionally, the oscillators are drawn over them like in the
+//! preview, see [`preview::paint`].
+
+use crate::cli::AnnotateArgs;
+use crate::glyphs;
+use crate::raster::{draw_line, draw_text, fill_rect};
+use image::buffer::ConvertBuffer;
+use image::{Rgb, RgbImage};
+use jump_rope::activity::Activity;
+use jump_rope::frame::VideoWriter;
+use jump_rope::pipeline::{Pipeline, PipelineConfig};
+use jump_rope::preview::{self, Preview};
+use jump_rope::{
+    Result, HIGHEST_FREQUENCY_OF_INTEREST, LOWEST_FREQUENCY_OF_INTEREST,
+};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How many seconds of the cadence the graph in the corner shows.
+const GRAPH_SECONDS: f32 = 30.0;
+
+/// How much the panels under the text and the graph cover the frame.
+const PANEL_OPACITY: f32 = 0.8;
+
+const PANEL_COLOR: Rgb<u8> = Rgb([255, 255, 255]);
+
+const TEXT_COLOR: Rgb<u8> = Rgb([40, 40, 40]);
+
+/// The cadence and the recent cadence drawn over each frame.
+pub struct Overlay {
+    // Cadence at each frame of the last [`GRAPH_SECONDS`], if the athlete was
+    // jumping.
+    history: VecDeque<(Duration, Option<f32>)>,
+}
+
+/// Analyses the source and writes it with the overlay into the output video.
+pub fn run(args: AnnotateArgs) -> Result<()> {
+    let frames = args.source.open()?;
+    let frame_rate = frames.frame_rate();
+    let (width, height) = (frames.width(), frames.height());
+
+    // the oscillators are placed by the seed, the video must be repeatable
+    let config = PipelineConfig::default();
+    let mut pipeline = Pipeline::from_source(frames.as_ref(), &config)?;
+    let preview = if args.heatmap {
+        let preview = Arc::new(Preview::new());
+        pipeline = pipeline.with_preview(Arc::clone(&preview));
+        Some(preview)
+    } else {
+        None
+    };
+
+    let mut writer = VideoWriter::create(&args.out, width, height, frame_rate)?;
+    let mut overlay = Overlay::new();
+    for frame in frames {
+        let frame = Arc::new(frame);
+        pipeline.push_frame(Arc::clone(&frame))?;
+        let tracker = pipeline.tracker();
+        tracker.tick(frame.timestamp);
+
+        let cadence = match (tracker.activity(), tracker.calculate_latest()) {
+            (Activity::Jumping, Some(hz)) => Some(hz),
+            _ => None,
+        };
+        overlay.push(frame.timestamp, cadence);
+
+        let mut image: RgbImage = match &preview {
+            Some(preview) => {
+                let rgba = preview::paint(&frame.image, &preview.votes());
+                let rgb = rgba
+                    .chunks_exact(4)
+                    .flat_map(|pixel| pixel[..3].iter().copied())
+                    .collect();
+                RgbImage::from_raw(width, height, rgb)
+                    .expect("Preview doesn't match the frame")
+            }
+            None => frame.image.convert(),
+        };
+        overlay.draw(&mut image, tracker.snapshot().jump_count);
+        writer.write(&image)?;
+    }
+
+    writer.finish()?;
+    println!("Annotated video written to {:?}", args.out);
+
+    Ok(())
+}
+
+impl Overlay {
+    pub fn new() -> Self {
+        Self {
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Remembers the cadence at given frame, None if the athlete isn't
+    /// jumping.
+    pub fn push(&mut self, timestamp: Duration, cadence: Option<f32>) {
+        self.history.push_back((timestamp, cadence));
+
+        let keep = Duration::from_secs_f32(GRAPH_SECONDS);
+        while let Some((oldest, _)) = self.history.front() {
+            if timestamp.saturating_sub(*oldest) <= keep {
+                break;
+            }
+            self.history.pop_front();
+        }
+    }
+
+    /// Draws the latest cadence and given jump count in the top left corner,
+    /// and the graph of the recent cadence in the bottom right corner. The
+    /// sizes are relative to the frame height.
+    pub fn draw(&self, image: &mut RgbImage, jump_count: f32) {
+        let (width, height) = image.dimensions();
+        let (width, height) = (width as f32, height as f32);
+        let margin = height / 30.0;
+
+        // e.g. "150 BPM" and "312 jumps" below it
+        let cadence = self.history.back().and_then(|(_, hz)| *hz);
+        let bpm = match cadence {
+            Some(hz) => format!("{:.0} BPM", hz * 60.0),
+            None => "--- BPM".to_string(),
+        };
+        let jumps = format!("{:.0} jumps", jump_count);
+        let bpm_height = height / 12.0;
+        let jumps_height = bpm_height / 2.0;
+
+        // the descenders of the jump count reach a third of its height
+        let panel_width = glyphs::width(&bpm, bpm_height)
+            .max(glyphs::width(&jumps, jumps_height))
+            + 2.0 * margin;
+        let panel_height = bpm_height + jumps_height * 4.0 / 3.0 + 3.0 * margin;
+        fill_rect(
+            image,
+            (margin, margin),
+            (panel_width, panel_height),
+            PANEL_COLOR,
+            PANEL_OPACITY,
+        );
+
+        let left = 2.0 * margin;
+        let baseline = 2.0 * margin + bpm_height;
+        draw_text(image, &bpm, (left, baseline), bpm_height, TEXT_COLOR);
+        let baseline = baseline + margin + jumps_height;
+        draw_text(image, &jumps, (left, baseline), jumps_height, TEXT_COLOR);
+
+        // the latest cadence is at the right edge of the graph
+        let size = (width / 3.0, height / 5.0);
+        let corner = (width - margin - size.0, height - margin - size.1);
+        fill_rect(image, corner, size, PANEL_COLOR, PANEL_OPACITY);
+
+        let now = match self.history.back() {
+            Some((now, _)) => *now,
+            None => return,
+        };
+        let range =
+            HIGHEST_FREQUENCY_OF_INTEREST - LOWEST_FREQUENCY_OF_INTEREST;
+        let to_point = |(timestamp, hz): (Duration, f32)| {
+            let age = now.saturating_sub(timestamp).as_secs_f32();
+            let progress = (hz - LOWEST_FREQUENCY_OF_INTEREST) / range;
+            (
+                corner.0 + size.0 * (1.0 - age / GRAPH_SECONDS),
+                corner.1 + size.1 * (1.0 - progress.clamp(0.0, 1.0)),
+            )
+        };
+
+        let thickness = (height / 240.0).max(1.0);
+        let points = self
+            .history
+            .iter()
+            .map(|(timestamp, hz)| hz.map(|hz| to_point((*timestamp, hz))));
+        let mut previous = None;
+        for point in points {
+            if let (Some(from), Some(to)) = (previous, point) {
+                draw_line(image, from, to, thickness, TEXT_COLOR);
+            }
+            previous = point;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_draws_overlay() {
+        let mut overlay = Overlay::new();
+        overlay.push(Duration::from_secs(0), Some(1.0));
+        // the first cadence is older than the graph shows
+        for second in 10..=40 {
+            let cadence = if second > 20 { Some(2.5) } else { None };
+            overlay.push(Duration::from_secs(second), cadence);
+        }
+        assert_eq!(overlay.history.len(), 31);
+
+        let mut image = RgbImage::new(300, 240);
+        overlay.draw(&mut image, 50.0);
+
+        // the frame shows through the panels, and elsewhere it's untouched
+        assert_eq!(image.get_pixel(9, 9), &Rgb([204, 204, 204]));
+        assert_eq!(image.get_pixel(150, 120), &Rgb([0, 0, 0]));
+
+        // the graph is 100x48 in the bottom right corner, and the athlete
+        // has been jumping for its last 19 seconds
+        let curve = |xs: std::ops::Range<u32>| {
+            xs.flat_map(|x| (184..232).map(move |y| (x, y)))
+                .filter(|(x, y)| image.get_pixel(*x, *y) == &TEXT_COLOR)
+                .count()
+        };
+        assert_eq!(curve(192..225), 0);
+        assert!(curve(230..292) > 50);
+    }
+}
@@ -0,0 +1,66 @@
+//! Errors of the app around the analysis, e.g. of the session history or the
+//! audio output, which the library knows nothing about.
+
+use thiserror::Error;
+use tungstenite::handshake::{HandshakeError, HandshakeRole};
+
+pub type AppResult<T> = std::result::Result<T, AppError>;
+
+#[derive(Debug, Error)]
+pub enum AppError {
+    /// See [`jump_rope::Error`].
+    #[error(transparent)]
+    Analysis(#[from] jump_rope::Error),
+    #[error("History: {0}")]
+    History(#[from] rusqlite::Error),
+    #[error("TOML: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("MIDI: {0}")]
+    Midi(String),
+    #[error("Audio: {0}")]
+    Audio(String),
+    // boxed as it's much larger than the others
+    #[error("WebSocket: {0}")]
+    WebSocket(Box<tungstenite::Error>),
+    /// A client of the server sent a request which cannot be served.
+    #[error("Bad request: {0}")]
+    BadRequest(String),
+}
+
+impl From<midir::InitError> for AppError {
+    fn from(e: midir::InitError) -> Self {
+        Self::Midi(e.to_string())
+    }
+}
+
+impl From<tungstenite::Error> for AppError {
+    fn from(e: tungstenite::Error) -> Self {
+        Self::WebSocket(Box::new(e))
+    }
+}
+
+impl<Role: HandshakeRole> From<HandshakeError<Role>> for AppError {
+    fn from(e: HandshakeError<Role>) -> Self {
+        match e {
+            HandshakeError::Failure(e) => e.into(),
+            // only non-blocking streams are interrupted
+            HandshakeError::Interrupted(_) => {
+                Self::BadRequest("WebSocket handshake interrupted".to_string())
+            }
+        }
+    }
+}
+
+// the app's own files, e.g. the trace or the history, fail like the
+// library's would
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Analysis(e.into())
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Analysis(e.into())
+    }
+}
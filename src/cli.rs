@@ -0,0 +1,349 @@
+use crate::devices::{self, CaptureOptions};
+use jump_rope::frame::FrameIter;
+use jump_rope::source::{CadenceScene, FrameSource, ImageSequence, RawFrames};
+use jump_rope::{Error, Result, MIN_FRAME_RATE};
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Estimates jump rope cadence from video")]
+pub struct Args {
+    #[structopt(flatten)]
+    pub source: SourceArgs,
+    /// Analyses another source at the same time, e.g. a second camera from
+    /// the front, with the same options as the first one. The cadence is
+    /// fused from the estimates of all sources by their confidence. Can be
+    /// given more than once.
+    #[structopt(long)]
+    pub add_source: Vec<String>,
+    /// Records the reports and consensus values of the session into a trace
+    /// file, which can be replayed later.
+    #[structopt(long, parse(from_os_str))]
+    pub record: Option<PathBuf>,
+    /// Writes the frequency timeline into a ".csv" or ".jsonl" file during
+    /// the session.
+    #[structopt(long, parse(from_os_str))]
+    pub export: Option<PathBuf>,
+    /// Serves the live cadence on given address, e.g. "0.0.0.0:8080", as a
+    /// JSON snapshot on "/snapshot" and a WebSocket stream on "/ws".
+    #[structopt(long)]
+    pub serve: Option<String>,
+    /// Serves Prometheus metrics on the health of the pipeline on given
+    /// address, e.g. "0.0.0.0:9090".
+    #[structopt(long)]
+    pub metrics: Option<String>,
+    /// Sends the cadence as OSC messages over UDP to given address, e.g.
+    /// "127.0.0.1:9000".
+    #[structopt(long)]
+    pub osc: Option<String>,
+    /// Sends MIDI clock following the cadence to a MIDI port of given name.
+    #[structopt(long)]
+    pub midi_clock: Option<String>,
+    /// Plays a metronome at given target cadence in BPM, with a distinct cue
+    /// while the cadence is off pace.
+    #[structopt(long, parse(try_from_str = parse_bpm))]
+    pub metronome: Option<f32>,
+    /// How many BPM the cadence may differ from the metronome.
+    #[structopt(long, default_value = "6")]
+    pub tolerance: f32,
+    /// Guides through the workout defined in given TOML file.
+    #[structopt(long, parse(from_os_str))]
+    pub workout: Option<PathBuf>,
+    /// Stores the session of the athlete with given name in the history.
+    #[structopt(long)]
+    pub athlete: Option<String>,
+    /// Updates the weight of the athlete in their profile.
+    #[structopt(long)]
+    pub weight_kg: Option<f32>,
+    /// SQLite database with the history of sessions. Defaults to
+    /// "$XDG_DATA_HOME/jump-rope/sessions.sqlite".
+    #[structopt(long, parse(from_os_str))]
+    pub history: Option<PathBuf>,
+    /// Plots the estimate of each window size along the consensus curve. The
+    /// "D" key toggles them in the graph.
+    #[structopt(long)]
+    pub show_windows: bool,
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Args {
+    /// The first source followed by the added ones, see
+    /// [`Self::add_source`].
+    pub fn sources(&self) -> Vec<SourceArgs> {
+        let mut sources = vec![self.source.clone()];
+        for source in &self.add_source {
+            sources.push(SourceArgs {
+                source: source.clone(),
+                ..self.source.clone()
+            });
+        }
+
+        sources
+    }
+}
+
+/// Without any command, the live view of the source is shown.
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Runs the analysis offline over labelled videos and reports accuracy.
+    Evaluate(EvaluateArgs),
+    /// Shows a recorded trace in the live view instead of analysing video.
+    Replay(ReplayArgs),
+    /// Converts a recorded trace into a ".csv" or ".jsonl" frequency timeline.
+    Export(ExportArgs),
+    /// Renders the metronome following a recorded trace into a WAV file.
+    Metronome(MetronomeArgs),
+    /// Lists, compares and charts sessions stored with "--athlete".
+    History(HistoryCommand),
+    /// Renders the source with the cadence burned in into a video file.
+    Annotate(AnnotateArgs),
+    /// Lists the video devices with the formats, sizes and frame rates they
+    /// capture in.
+    Devices,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct EvaluateArgs {
+    /// Frame rate of image sequences, which don't carry it.
+    #[structopt(long)]
+    pub fps: Option<usize>,
+    /// Videos or image sequences to evaluate. Each must have a labels file
+    /// next to it with the same name and the ".labels" extension.
+    #[structopt(required = true)]
+    pub videos: Vec<String>,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ReplayArgs {
+    /// Trace recorded with the "--record" option.
+    #[structopt(parse(from_os_str))]
+    pub trace: PathBuf,
+    /// How many times faster than recorded to replay.
+    #[structopt(
+        long,
+        default_value = "1.0",
+        parse(try_from_str = parse_speed)
+    )]
+    pub speed: f32,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ExportArgs {
+    /// Trace recorded with the "--record" option.
+    #[structopt(parse(from_os_str))]
+    pub trace: PathBuf,
+    /// Where to write the timeline. The format is given by the extension,
+    /// ".svg" and ".png" files get a graph of the cadence.
+    #[structopt(parse(from_os_str))]
+    pub out: PathBuf,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct MetronomeArgs {
+    /// Trace recorded with the "--record" option.
+    #[structopt(parse(from_os_str))]
+    pub trace: PathBuf,
+    /// Where to write the ".wav" file.
+    #[structopt(parse(from_os_str))]
+    pub out: PathBuf,
+    /// Target cadence in BPM.
+    #[structopt(long, parse(try_from_str = parse_bpm))]
+    pub bpm: f32,
+    /// How many BPM the cadence may differ from the target.
+    #[structopt(long, default_value = "6")]
+    pub tolerance: f32,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct AnnotateArgs {
+    #[structopt(flatten)]
+    pub source: SourceArgs,
+    /// Where to write the video, e.g. "annotated.mp4".
+    #[structopt(short, long, parse(from_os_str))]
+    pub out: PathBuf,
+    /// Draws the oscillators over the video, coloured by the frequency they
+    /// vote for.
+    #[structopt(long)]
+    pub heatmap: bool,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum HistoryCommand {
+    /// Lists past sessions from the newest.
+    List {
+        /// Only lists sessions of this athlete.
+        #[structopt(long)]
+        athlete: Option<String>,
+    },
+    /// Compares two sessions side by side.
+    Compare { first: i64, second: i64 },
+    /// Charts the jumps of the athlete in each of the recent weeks.
+    Progress {
+        athlete: String,
+        #[structopt(long, default_value = "12")]
+        weeks: usize,
+    },
+}
+
+/// Where to read the frames from.
+#[derive(Debug, Clone, StructOpt)]
+pub struct SourceArgs {
+    /// Video device, video file, directory of numbered PNG/JPEG frames, "-"
+    /// to read raw 8 bit grayscale frames from stdin, or "synthetic:HZ" to
+    /// generate a scene jumping at given cadence.
+    #[structopt(default_value = "/dev/video0")]
+    pub source: String,
+    /// Frame rate of image sequences, raw and generated frames, which don't
+    /// carry it, or to capture devices at.
+    #[structopt(long)]
+    pub fps: Option<usize>,
+    /// Width of raw or generated frames, or to capture devices at.
+    #[structopt(long)]
+    pub width: Option<u32>,
+    /// Height of raw or generated frames, or to capture devices at.
+    #[structopt(long)]
+    pub height: Option<u32>,
+    /// Pixel format to capture devices in, e.g. "MJPG". Devices often capture
+    /// faster in compressed formats, see the "devices" command.
+    #[structopt(long)]
+    pub pixel_format: Option<String>,
+}
+
+impl SourceArgs {
+    /// Devices are opened again when lost, e.g. the camera was unplugged.
+    pub fn is_device(&self) -> bool {
+        self.source.starts_with("/dev/")
+    }
+
+    /// Must be called on the thread which reads the frames, see
+    /// [`FrameIter`]. Fails if the frame rate is below [`MIN_FRAME_RATE`].
+    pub fn open(&self) -> Result<Box<dyn FrameSource>> {
+        // before a device is asked for it
+        if let Some(fps) = self.fps {
+            check_frame_rate(fps)?;
+        }
+        let frames = self.open_any()?;
+        check_frame_rate(frames.frame_rate())?;
+
+        Ok(frames)
+    }
+
+    fn open_any(&self) -> Result<Box<dyn FrameSource>> {
+        if self.source == "-" {
+            let (width, height, fps) = match (self.width, self.height, self.fps)
+            {
+                (Some(w), Some(h), Some(fps)) => (w, h, fps),
+                _ => {
+                    return Err(Error::InvalidArgument(
+                        "Raw frames need --width, --height and --fps"
+                            .to_string(),
+                    ))
+                }
+            };
+
+            Ok(Box::new(RawFrames::from_stdin(width, height, fps)))
+        } else if let Some(hz) = self.source.strip_prefix("synthetic:") {
+            let hz: f32 = hz.parse()?;
+            let scene = CadenceScene::constant(hz).with_noise(10);
+
+            let frames = scene.into_source(
+                self.width.unwrap_or(320),
+                self.height.unwrap_or(240),
+                self.fps.unwrap_or(30),
+            );
+
+            Ok(Box::new(frames.paced()))
+        } else if self.is_device() {
+            let requested = CaptureOptions {
+                size: match (self.width, self.height) {
+                    (Some(w), Some(h)) => Some((w, h)),
+                    (None, None) => None,
+                    _ => {
+                        return Err(Error::InvalidArgument(
+                            "Devices need both --width and --height"
+                                .to_string(),
+                        ))
+                    }
+                },
+                frame_rate: self.fps,
+                pixel_format: self.pixel_format.clone(),
+            };
+            // without a request, the device captures in its current format
+            let capture = if requested == CaptureOptions::default() {
+                requested
+            } else {
+                devices::negotiate(&self.source, &requested)?
+            };
+
+            Ok(Box::new(FrameIter::from_device(
+                &self.source,
+                &capture.ffmpeg_options(),
+            )?))
+        } else if Path::new(&self.source).is_dir() {
+            let fps = self.fps.ok_or_else(|| {
+                Error::InvalidArgument("Image sequences need --fps".to_string())
+            })?;
+
+            Ok(Box::new(ImageSequence::from_dir(&self.source, fps)?))
+        } else {
+            Ok(Box::new(FrameIter::from_file(&self.source)?))
+        }
+    }
+}
+
+fn parse_speed(speed: &str) -> Result<f32> {
+    let speed: f32 = speed.parse()?;
+    if !(speed.is_finite() && speed > 0.0) {
+        return Err(Error::InvalidArgument(
+            "Speed must be a positive number".to_string(),
+        ));
+    }
+
+    Ok(speed)
+}
+
+// A metronome at 0 BPM would never click.
+fn parse_bpm(bpm: &str) -> Result<f32> {
+    let bpm: f32 = bpm.parse()?;
+    if !(bpm.is_finite() && bpm > 0.0) {
+        return Err(Error::InvalidArgument(
+            "Cadence must be a positive number of BPM".to_string(),
+        ));
+    }
+
+    Ok(bpm)
+}
+
+// The analyzers cannot report slower frames, see [`MIN_FRAME_RATE`].
+fn check_frame_rate(fps: usize) -> Result<()> {
+    if fps < MIN_FRAME_RATE {
+        return Err(Error::FrameRateTooLow {
+            fps,
+            min: MIN_FRAME_RATE,
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_rejects_non_positive_bpm() {
+        assert_eq!(parse_bpm("150").unwrap(), 150.0);
+        for bpm in &["0", "-120", "NaN", "inf"] {
+            assert!(matches!(parse_bpm(bpm), Err(Error::InvalidArgument(_))));
+        }
+        assert!(MetronomeArgs::from_iter_safe(&[
+            "metronome",
+            "session.trace",
+            "out.wav",
+            "--bpm",
+            "0",
+        ])
+        .is_err());
+    }
+}
@@ -0,0 +1,416 @@
+//! The video devices on the machine and the formats they capture in.
+//!
+//! Cameras often capture larger or faster frames only in some pixel formats,
+//! e.g. 60 fps in MJPEG but 30 fps uncompressed. As higher frame rates help to
+//! resolve fast cadences, the capture format can be requested, see
+//! [`negotiate`]. The devices are enumerated with V4L2, therefore only on
+//! Linux.
+
+use jump_rope::{Error, Result};
+use std::fmt;
+use std::path::PathBuf;
+
+/// Names which ffmpeg knows the V4L2 pixel formats by, for its
+/// "input_format" option.
+const PIXEL_FORMATS: &[(&str, &str)] = &[
+    ("YUYV", "yuyv422"),
+    ("UYVY", "uyvy422"),
+    ("YU12", "yuv420p"),
+    ("NV12", "nv12"),
+    ("GREY", "gray"),
+    ("RGB3", "rgb24"),
+    ("MJPG", "mjpeg"),
+    ("H264", "h264"),
+];
+
+/// A video capture device, e.g. "/dev/video0".
+#[derive(Debug, Clone)]
+pub struct Device {
+    pub path: PathBuf,
+    pub name: String,
+    pub formats: Vec<Format>,
+}
+
+/// A pixel format the device captures in.
+#[derive(Debug, Clone)]
+pub struct Format {
+    /// Four character code of the format, e.g. "YUYV" or "MJPG".
+    pub fourcc: String,
+    pub description: String,
+    pub modes: Vec<Mode>,
+}
+
+/// A frame size the device captures in, and the frame rates at that size.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mode {
+    pub width: u32,
+    pub height: u32,
+    pub frame_rates: Vec<f32>,
+}
+
+/// What to ask the device for. Anything left out is up to the device.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CaptureOptions {
+    pub size: Option<(u32, u32)>,
+    pub frame_rate: Option<usize>,
+    /// The four character code as listed by [`list`], e.g. "MJPG", or the
+    /// name ffmpeg knows the format by, e.g. "mjpeg".
+    pub pixel_format: Option<String>,
+}
+
+/// Prints the capture devices with their formats, sizes and frame rates.
+pub fn run() -> Result<()> {
+    let devices = list()?;
+    if devices.is_empty() {
+        println!("No video devices found");
+    }
+    for device in devices {
+        print!("{}", device);
+    }
+
+    Ok(())
+}
+
+/// Capture devices ordered by their path. Devices which don't capture any
+/// frames, e.g. the metadata nodes of cameras, are left out, as are devices
+/// which cannot be asked, e.g. ones in use by another program.
+#[cfg(target_os = "linux")]
+pub fn list() -> Result<Vec<Device>> {
+    let mut paths: Vec<_> = v4l::context::enum_devices()
+        .iter()
+        .map(|node| node.path().to_path_buf())
+        .collect();
+    paths.sort();
+
+    let mut devices = vec![];
+    for path in paths {
+        let device = match describe(&path) {
+            Ok(device) => device,
+            Err(e) => {
+                // one busy camera shouldn't hide the others
+                eprintln!("Skipping {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        if !device.formats.is_empty() {
+            devices.push(device);
+        }
+    }
+
+    Ok(devices)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn list() -> Result<Vec<Device>> {
+    Err(Error::Unsupported(
+        "Video devices can only be listed on Linux".to_string(),
+    ))
+}
+
+/// Asks the device at given path about its formats.
+#[cfg(target_os = "linux")]
+pub fn describe(path: impl Into<PathBuf>) -> Result<Device> {
+    use v4l::frameinterval::FrameIntervalEnum;
+    use v4l::framesize::FrameSizeEnum;
+    use v4l::video::Capture;
+
+    let path = path.into();
+    let device = v4l::Device::with_path(&path)?;
+    let name = v4l::context::Node::new(&path).name().unwrap_or_default();
+
+    let mut formats = vec![];
+    for description in device.enum_formats()? {
+        let fourcc = description.fourcc;
+
+        // not every driver enumerates the sizes and intervals
+        let mut sizes = vec![];
+        for frame_size in device.enum_framesizes(fourcc).unwrap_or_default() {
+            match frame_size.size {
+                FrameSizeEnum::Discrete(size) => {
+                    sizes.push((size.width, size.height))
+                }
+                // all sizes in between would be too many to list
+                FrameSizeEnum::Stepwise(range) => {
+                    sizes.push((range.min_width, range.min_height));
+                    sizes.push((range.max_width, range.max_height));
+                }
+            }
+        }
+
+        let mut modes = vec![];
+        for (width, height) in sizes {
+            let mut frame_rates = vec![];
+            let intervals = device
+                .enum_frameintervals(fourcc, width, height)
+                .unwrap_or_default();
+            for frame_interval in intervals {
+                // the interval is in seconds per frame
+                let mut push = |interval: v4l::Fraction| {
+                    if interval.numerator > 0 {
+                        frame_rates.push(
+                            interval.denominator as f32
+                                / interval.numerator as f32,
+                        );
+                    }
+                };
+                match frame_interval.interval {
+                    FrameIntervalEnum::Discrete(interval) => push(interval),
+                    FrameIntervalEnum::Stepwise(range) => {
+                        push(range.min);
+                        push(range.max);
+                    }
+                }
+            }
+
+            modes.push(Mode {
+                width,
+                height,
+                frame_rates,
+            });
+        }
+
+        formats.push(Format {
+            fourcc: fourcc.to_string(),
+            description: description.description,
+            modes,
+        });
+    }
+
+    Ok(Device {
+        path,
+        name,
+        formats,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn describe(_path: impl Into<PathBuf>) -> Result<Device> {
+    Err(Error::Unsupported(
+        "Video devices can only be described on Linux".to_string(),
+    ))
+}
+
+/// Fills in what the request leaves out with the first mode of the device
+/// which satisfies it, or fails with the modes the device supports.
+pub fn negotiate(
+    path: impl Into<PathBuf>,
+    requested: &CaptureOptions,
+) -> Result<CaptureOptions> {
+    let device = describe(path)?;
+    device.negotiate(requested).ok_or_else(|| {
+        Error::Unsupported(format!(
+            "Device cannot capture {}, it supports:\n{}",
+            requested, device
+        ))
+    })
+}
+
+impl Device {
+    /// The first mode in the order the device lists them which satisfies the
+    /// request, with the size and the pixel format filled in. The frame rate
+    /// is left to the device unless requested.
+    pub fn negotiate(
+        &self,
+        requested: &CaptureOptions,
+    ) -> Option<CaptureOptions> {
+        let (format, mode) = self
+            .formats
+            .iter()
+            .filter(|format| match &requested.pixel_format {
+                Some(pixel_format) => format.is(pixel_format),
+                None => true,
+            })
+            .flat_map(|format| format.modes.iter().map(move |m| (format, m)))
+            .filter(|(_, mode)| match requested.size {
+                Some(size) => size == (mode.width, mode.height),
+                None => true,
+            })
+            .find(|(_, mode)| match requested.frame_rate {
+                // unknown if the driver doesn't enumerate them
+                Some(fps) => {
+                    mode.frame_rates.is_empty()
+                        || mode
+                            .frame_rates
+                            .iter()
+                            .any(|rate| rate.round() as usize == fps)
+                }
+                None => true,
+            })?;
+
+        Some(CaptureOptions {
+            size: Some((mode.width, mode.height)),
+            frame_rate: requested.frame_rate,
+            pixel_format: Some(format.fourcc.clone()),
+        })
+    }
+}
+
+impl Format {
+    // Whether the format is known by given four character code or ffmpeg
+    // name, ignoring the case.
+    fn is(&self, name: &str) -> bool {
+        self.fourcc.eq_ignore_ascii_case(name)
+            || matches!(
+                ffmpeg_name(&self.fourcc),
+                Some(ffmpeg) if ffmpeg.eq_ignore_ascii_case(name)
+            )
+    }
+}
+
+impl CaptureOptions {
+    /// Options of the ffmpeg V4L2 input which request the capture format, see
+    /// [`FrameIter::from_device`].
+    ///
+    /// [`FrameIter::from_device`]: jump_rope::frame::FrameIter::from_device
+    pub fn ffmpeg_options(&self) -> Vec<(&'static str, String)> {
+        let mut options = vec![];
+        if let Some((width, height)) = self.size {
+            options.push(("video_size", format!("{}x{}", width, height)));
+        }
+        if let Some(fps) = self.frame_rate {
+            options.push(("framerate", fps.to_string()));
+        }
+        if let Some(pixel_format) = &self.pixel_format {
+            let name = ffmpeg_name(pixel_format).unwrap_or(pixel_format);
+            options.push(("input_format", name.to_string()));
+        }
+
+        options
+    }
+}
+
+// e.g. "yuyv422" for "YUYV"
+fn ffmpeg_name(fourcc: &str) -> Option<&'static str> {
+    PIXEL_FORMATS
+        .iter()
+        .find(|(code, _)| code.eq_ignore_ascii_case(fourcc))
+        .map(|(_, name)| *name)
+}
+
+impl fmt::Display for Device {
+    // e.g.
+    //
+    // /dev/video0: Integrated Camera
+    //   MJPG (Motion-JPEG)
+    //     1280x720 at 60, 30 fps
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}: {}", self.path.display(), self.name)?;
+        for format in &self.formats {
+            writeln!(f, "  {} ({})", format.fourcc, format.description)?;
+            for mode in &format.modes {
+                let rates: Vec<_> = mode
+                    .frame_rates
+                    .iter()
+                    .map(|rate| format_frame_rate(*rate))
+                    .collect();
+                writeln!(
+                    f,
+                    "    {}x{} at {} fps",
+                    mode.width,
+                    mode.height,
+                    rates.join(", ")
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// e.g. "30" but "29.97"
+fn format_frame_rate(rate: f32) -> String {
+    let rate = format!("{:.2}", rate);
+    rate.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+impl fmt::Display for CaptureOptions {
+    // e.g. "1280x720 at 60 fps in MJPG"
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = vec![];
+        if let Some((width, height)) = self.size {
+            parts.push(format!("{}x{}", width, height));
+        }
+        if let Some(fps) = self.frame_rate {
+            parts.push(format!("at {} fps", fps));
+        }
+        if let Some(pixel_format) = &self.pixel_format {
+            parts.push(format!("in {}", pixel_format));
+        }
+
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_negotiates_capture_format() {
+        let mode = |width, height, frame_rates: &[f32]| Mode {
+            width,
+            height,
+            frame_rates: frame_rates.to_vec(),
+        };
+        let device = Device {
+            path: PathBuf::from("/dev/video0"),
+            name: "Camera".to_string(),
+            formats: vec![
+                Format {
+                    fourcc: "YUYV".to_string(),
+                    description: "YUYV 4:2:2".to_string(),
+                    modes: vec![
+                        mode(640, 480, &[30.0]),
+                        mode(1280, 720, &[10.0]),
+                    ],
+                },
+                Format {
+                    fourcc: "MJPG".to_string(),
+                    description: "Motion-JPEG".to_string(),
+                    modes: vec![mode(1280, 720, &[60.0, 29.97])],
+                },
+            ],
+        };
+
+        // only the compressed frames are fast enough
+        let requested = CaptureOptions {
+            frame_rate: Some(60),
+            ..Default::default()
+        };
+        let negotiated = device.negotiate(&requested).unwrap();
+        assert_eq!(negotiated.to_string(), "1280x720 at 60 fps in MJPG");
+        assert_eq!(
+            negotiated.ffmpeg_options(),
+            vec![
+                ("video_size", "1280x720".to_string()),
+                ("framerate", "60".to_string()),
+                ("input_format", "mjpeg".to_string()),
+            ]
+        );
+
+        let requested = CaptureOptions {
+            size: Some((1280, 720)),
+            pixel_format: Some("yuyv422".to_string()),
+            ..Default::default()
+        };
+        let negotiated = device.negotiate(&requested).unwrap();
+        assert_eq!(negotiated.pixel_format.as_deref(), Some("YUYV"));
+
+        let requested = CaptureOptions {
+            size: Some((640, 480)),
+            frame_rate: Some(60),
+            ..Default::default()
+        };
+        assert_eq!(device.negotiate(&requested), None);
+
+        assert_eq!(
+            device.to_string(),
+            "/dev/video0: Camera\n  \
+             YUYV (YUYV 4:2:2)\n    \
+             640x480 at 30 fps\n    \
+             1280x720 at 10 fps\n  \
+             MJPG (Motion-JPEG)\n    \
+             1280x720 at 60, 29.97 fps\n"
+        );
+    }
+}
@@ -0,0 +1,86 @@
+//! Everything which can go wrong, so that callers can tell a camera which went
+//! away from a malformed file.
+
+use std::io;
+use std::num::{ParseFloatError, ParseIntError};
+use std::sync::mpsc::{RecvError, SendError};
+use thiserror::Error;
+
+/// More variants may be added, so matches need a wildcard arm.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// The source stopped yielding frames before its end, e.g. the camera
+    /// was unplugged.
+    #[error("Video source disconnected: {0}")]
+    Disconnected(String),
+    /// A thread of the analysis stopped, which only happens if it panicked.
+    #[error("Analysis thread stopped")]
+    ThreadStopped,
+    #[error("Video: {0}")]
+    Video(#[from] ffmpeg::Error),
+    #[error("Image: {0}")]
+    Image(#[from] image::ImageError),
+    #[error("I/O: {0}")]
+    Io(#[from] io::Error),
+    #[error("JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    /// A command line option or a setting doesn't make sense, e.g. a missing
+    /// option, a negative speed or a workout without intervals.
+    #[error("{0}")]
+    InvalidArgument(String),
+    /// A line of a trace or a labels file cannot be parsed. Lines are
+    /// numbered from 1.
+    #[error("Line {line}: {message}")]
+    MalformedLine { line: usize, message: String },
+    /// A file parses line by line, but doesn't make sense as a whole, e.g.
+    /// the labels are out of order.
+    #[error("{0}")]
+    MalformedFile(String),
+    /// The source is too slow to analyse, as the analyzers report at most
+    /// once per frame.
+    #[error("Cannot analyse {fps} fps, at least {min} fps are needed")]
+    FrameRateTooLow { fps: usize, min: usize },
+    /// A source doesn't run at the frame rate the analysis was set up for,
+    /// e.g. a camera reconnected at another one, or the sources differ.
+    #[error("Expected {expected} fps, the source runs at {actual} fps")]
+    FrameRateMismatch { expected: usize, actual: usize },
+    /// A frame isn't of the size the analysis was set up for, as width and
+    /// height.
+    #[error("Expected a frame of {expected:?}, got {actual:?}")]
+    FrameSizeMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    /// The file format, the platform or the device doesn't support what was
+    /// asked for.
+    #[error("{0}")]
+    Unsupported(String),
+}
+
+// numbers are parsed from the command line, files parse them themselves with
+// their line numbers
+impl From<ParseFloatError> for Error {
+    fn from(e: ParseFloatError) -> Self {
+        Self::InvalidArgument(e.to_string())
+    }
+}
+
+impl From<ParseIntError> for Error {
+    fn from(e: ParseIntError) -> Self {
+        Self::InvalidArgument(e.to_string())
+    }
+}
+
+// the other end of a channel is gone only when its thread stopped
+impl From<RecvError> for Error {
+    fn from(_: RecvError) -> Self {
+        Self::ThreadStopped
+    }
+}
+
+impl<T> From<SendError<T>> for Error {
+    fn from(_: SendError<T>) -> Self {
+        Self::ThreadStopped
+    }
+}
@@ -0,0 +1,442 @@
+//! Runs the pipeline offline over labelled sessions and measures how far the
+//! consensus frequency is from the ground truth.
+//!
+//! Each video is accompanied by a labels file with the same path but the
+//! `.labels` extension, e.g. `session_1.mp4` and `session_1.labels`. The file
+//! contains either one jump timestamp in seconds per line, or a cadence curve
+//! with one `seconds,hz` point per line. Empty lines and lines starting with
+//! `#` are ignored.
+
+use crate::cli::{EvaluateArgs, SourceArgs};
+use jump_rope::pipeline::{Pipeline, PipelineConfig};
+use jump_rope::rhythm::{Rhythm, RhythmStats};
+use jump_rope::{Error, Result};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// When ground truth cadence moves by at least this many Hz away from where it
+/// settled, we consider it a tempo change and measure how long the estimate
+/// takes to follow.
+const TEMPO_CHANGE_HZ: f32 = 0.3;
+
+/// An estimate within this many Hz of the ground truth has caught up with a
+/// tempo change.
+const CAUGHT_UP_HZ: f32 = 0.15;
+
+/// What actually happened in the session.
+#[derive(Debug)]
+pub enum GroundTruth {
+    /// Timestamps in seconds of each jump, in strictly ascending order.
+    Jumps(Vec<f32>),
+    /// Points `(seconds, hz)` of the cadence, in ascending order of time. The
+    /// cadence is linearly interpolated between them.
+    Cadence(Vec<(f32, f32)>),
+}
+
+/// How the estimate fared against the ground truth.
+#[derive(Debug)]
+pub struct Evaluation {
+    /// Mean absolute error in Hz over the time both the estimate and the
+    /// ground truth have a value.
+    pub mae: Option<f32>,
+    /// Fraction of the time with ground truth cadence that there also was an
+    /// estimate.
+    pub coverage: f32,
+    /// Average seconds it took the estimate to follow a tempo change.
+    pub mean_lag: Option<f32>,
+    /// How many tempo changes there were in the ground truth.
+    pub tempo_changes: usize,
+    /// Tempo changes which the estimate never caught up with.
+    pub missed_tempo_changes: usize,
+    /// Jumps counted by integrating the estimated cadence over time.
+    pub estimated_count: f32,
+    pub true_count: f32,
+    /// Steadiness of the estimate at the end of the video, which is taken as
+    /// a single set.
+    pub rhythm: RhythmStats,
+}
+
+/// Evaluates each video given in the arguments and prints the results.
+pub fn run(args: EvaluateArgs) -> Result<()> {
+    println!(
+        "{:<32} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}",
+        "session",
+        "MAE Hz",
+        "coverage",
+        "lag s",
+        "missed",
+        "count",
+        "truth",
+        "var %",
+        "drift",
+        "fatigue"
+    );
+
+    let mut evaluations = vec![];
+    for video in &args.videos {
+        let labels = Path::new(video).with_extension("labels");
+        let truth = GroundTruth::from_file(&labels)?;
+
+        let source = SourceArgs {
+            source: video.clone(),
+            fps: args.fps,
+            width: None,
+            height: None,
+            pixel_format: None,
+        };
+        let estimates = estimate(&source)?;
+
+        let evaluation = Evaluation::new(&truth, &estimates);
+        print_row(video, &evaluation);
+        evaluations.push(evaluation);
+    }
+
+    if evaluations.len() > 1 {
+        print_summary(&evaluations);
+    }
+
+    Ok(())
+}
+
+/// Runs the pipeline over the whole video and returns the consensus after
+/// each frame which produced any reports.
+pub fn estimate(source: &SourceArgs) -> Result<Vec<(Duration, Option<f32>)>> {
+    let frames = source.open()?;
+    // the oscillators are placed by the seed, evaluation must be repeatable
+    let config = PipelineConfig::default();
+    let mut pipeline = Pipeline::from_source(frames.as_ref(), &config)?;
+
+    let mut estimates = vec![];
+    for frame in frames {
+        let timestamp = frame.timestamp;
+        if !pipeline.push_frame(Arc::new(frame))?.is_empty() {
+            let consensus = pipeline.tracker().calculate_latest();
+            estimates.push((timestamp, consensus));
+        }
+    }
+
+    Ok(estimates)
+}
+
+impl GroundTruth {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(|e| {
+            Error::InvalidArgument(format!(
+                "Cannot read labels {:?}: {}",
+                path, e
+            ))
+        })?;
+
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut jumps = vec![];
+        let mut cadence = vec![];
+
+        let lines = contents
+            .lines()
+            .map(str::trim)
+            .enumerate()
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+        for (index, line) in lines {
+            let malformed = |message| Error::MalformedLine {
+                line: index + 1,
+                message,
+            };
+            let values = line
+                .split(',')
+                .map(|value| value.trim().parse::<f32>())
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .map_err(|e| {
+                    malformed(format!("Invalid label {:?}: {}", line, e))
+                })?;
+
+            match values.as_slice() {
+                [seconds] => jumps.push(*seconds),
+                [seconds, hz] => cadence.push((*seconds, *hz)),
+                _ => {
+                    return Err(malformed(format!("Invalid label {:?}", line)))
+                }
+            }
+        }
+
+        // a repeated jump would give an infinite cadence, while a repeated
+        // point of the curve is a step
+        let ascending = jumps.windows(2).all(|w| w[0] < w[1])
+            && cadence.windows(2).all(|w| w[0].0 <= w[1].0);
+
+        match (jumps.is_empty(), cadence.is_empty()) {
+            (true, true) => Err(Error::MalformedFile("No labels".to_string())),
+            (false, false) => Err(Error::MalformedFile(
+                "Cannot mix jump timestamps and cadence curve".to_string(),
+            )),
+            _ if !ascending => Err(Error::MalformedFile(
+                "Labels must be in ascending order of time".to_string(),
+            )),
+            (false, true) => Ok(Self::Jumps(jumps)),
+            (true, false) => Ok(Self::Cadence(cadence)),
+        }
+    }
+
+    /// Cadence in Hz at given time, if the athlete was jumping.
+    pub fn cadence_at(&self, seconds: f32) -> Option<f32> {
+        match self {
+            Self::Jumps(jumps) => {
+                // instantaneous cadence is given by the two jumps around
+                let next = jumps.iter().position(|t| *t > seconds)?;
+                let prev = jumps.get(next.checked_sub(1)?)?;
+
+                Some(1.0 / (jumps[next] - prev))
+            }
+            Self::Cadence(points) => {
+                let next = points.iter().position(|(t, _)| *t >= seconds)?;
+                let (t2, hz2) = points[next];
+                if next == 0 {
+                    return if t2 == seconds { Some(hz2) } else { None };
+                }
+
+                let (t1, hz1) = points[next - 1];
+                let progress = (seconds - t1) / (t2 - t1);
+                Some(hz1 + (hz2 - hz1) * progress)
+            }
+        }
+    }
+
+    /// How many jumps there were in the first given seconds of the session.
+    pub fn jump_count(&self, until: f32) -> f32 {
+        match self {
+            Self::Jumps(jumps) => {
+                jumps.iter().filter(|t| **t <= until).count() as f32
+            }
+            Self::Cadence(points) => points
+                .windows(2)
+                .map(|pair| {
+                    let ((t1, hz1), (t2, hz2)) = (pair[0], pair[1]);
+                    let t2_clamped = t2.min(until);
+                    if t2_clamped <= t1 {
+                        return 0.0;
+                    }
+
+                    // trapezoid between the two points cut at "until"
+                    let hz2_clamped =
+                        hz1 + (hz2 - hz1) * (t2_clamped - t1) / (t2 - t1);
+                    (hz1 + hz2_clamped) / 2.0 * (t2_clamped - t1)
+                })
+                .sum(),
+        }
+    }
+}
+
+impl Evaluation {
+    /// The estimates are consensus values in order of time. Each is valid
+    /// until the next one.
+    pub fn new(
+        truth: &GroundTruth,
+        estimates: &[(Duration, Option<f32>)],
+    ) -> Self {
+        let mut absolute_error_sum = 0.0;
+        let mut compared_samples = 0;
+        let mut samples_with_truth = 0;
+
+        // the cadence the ground truth settled on, and if it since changed,
+        // when did it happen
+        let mut settled_truth: Option<f32> = None;
+        let mut changed_at: Option<f32> = None;
+        let mut lags = vec![];
+        let mut tempo_changes = 0;
+
+        let mut estimated_count = 0.0;
+        let mut rhythm = Rhythm::new();
+
+        for (index, (timestamp, estimate)) in estimates.iter().enumerate() {
+            let seconds = timestamp.as_secs_f32();
+
+            // the estimate holds until the next one
+            if let (Some(hz), Some((next, _))) =
+                (estimate, estimates.get(index + 1))
+            {
+                estimated_count += hz * (next.as_secs_f32() - seconds);
+            }
+            if let Some(hz) = estimate {
+                rhythm.push(*timestamp, *hz);
+            }
+
+            let truth = match truth.cadence_at(seconds) {
+                Some(truth) => truth,
+                None => continue,
+            };
+            samples_with_truth += 1;
+
+            match settled_truth {
+                Some(settled) if (truth - settled).abs() < TEMPO_CHANGE_HZ => {}
+                Some(_) => {
+                    tempo_changes += 1;
+                    settled_truth = Some(truth);
+                    changed_at = Some(seconds);
+                }
+                None => settled_truth = Some(truth),
+            }
+
+            if let Some(estimate) = estimate {
+                let error = (estimate - truth).abs();
+                absolute_error_sum += error;
+                compared_samples += 1;
+
+                if error < CAUGHT_UP_HZ {
+                    if let Some(changed_at) = changed_at.take() {
+                        lags.push(seconds - changed_at);
+                    }
+                }
+            }
+        }
+
+        let end = estimates
+            .last()
+            .map(|(timestamp, _)| timestamp.as_secs_f32())
+            .unwrap_or_default();
+
+        Self {
+            mae: mean(absolute_error_sum, compared_samples),
+            coverage: if samples_with_truth > 0 {
+                compared_samples as f32 / samples_with_truth as f32
+            } else {
+                0.0
+            },
+            mean_lag: mean(lags.iter().sum(), lags.len()),
+            tempo_changes,
+            missed_tempo_changes: tempo_changes - lags.len(),
+            estimated_count,
+            true_count: truth.jump_count(end),
+            rhythm: rhythm.stats(),
+        }
+    }
+
+    /// Positive if the estimate counted more jumps than there were.
+    pub fn count_error(&self) -> f32 {
+        self.estimated_count - self.true_count
+    }
+}
+
+fn mean(sum: f32, count: usize) -> Option<f32> {
+    if count > 0 {
+        Some(sum / count as f32)
+    } else {
+        None
+    }
+}
+
+fn print_row(session: &str, evaluation: &Evaluation) {
+    let optional = |value: Option<f32>| {
+        value
+            .map(|v| format!("{:.3}", v))
+            .unwrap_or_else(|| "-".to_string())
+    };
+
+    let percent = |value: Option<f32>| optional(value.map(|v| v * 100.0));
+
+    println!(
+        "{:<32} {:>8} {:>8.2} {:>8} {:>8} {:>8.1} {:>8.1} {:>8} {:>8} {:>8}",
+        session,
+        optional(evaluation.mae),
+        evaluation.coverage,
+        optional(evaluation.mean_lag),
+        format!(
+            "{}/{}",
+            evaluation.missed_tempo_changes, evaluation.tempo_changes
+        ),
+        evaluation.estimated_count,
+        evaluation.true_count,
+        percent(evaluation.rhythm.variability),
+        optional(evaluation.rhythm.drift_hz_per_minute),
+        percent(evaluation.rhythm.fatigue),
+    );
+}
+
+fn print_summary(evaluations: &[Evaluation]) {
+    let average = |values: Vec<f32>| mean(values.iter().sum(), values.len());
+
+    let mae = average(evaluations.iter().filter_map(|e| e.mae).collect());
+    let lag = average(evaluations.iter().filter_map(|e| e.mean_lag).collect());
+    let count_error =
+        average(evaluations.iter().map(|e| e.count_error().abs()).collect());
+    let rhythm = |stat: fn(&RhythmStats) -> Option<f32>| {
+        average(evaluations.iter().filter_map(|e| stat(&e.rhythm)).collect())
+    };
+
+    println!();
+    println!("mean MAE: {:?} Hz", mae);
+    println!("mean lag: {:?} s", lag);
+    println!("mean absolute count error: {:?} jumps", count_error);
+    println!("mean variability: {:?}", rhythm(|r| r.variability));
+    println!("mean drift: {:?} Hz/min", rhythm(|r| r.drift_hz_per_minute));
+    println!("mean fatigue: {:?}", rhythm(|r| r.fatigue));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_labels() {
+        let jumps = GroundTruth::parse("# jumps\n1.0\n1.5\n\n2.0\n").unwrap();
+        assert_eq!(jumps.cadence_at(1.2), Some(2.0));
+        assert_eq!(jumps.cadence_at(0.5), None);
+        assert_eq!(jumps.cadence_at(2.5), None);
+        assert_eq!(jumps.jump_count(1.7), 2.0);
+
+        let curve = GroundTruth::parse("0, 2.0\n10, 2.0\n20, 3.0").unwrap();
+        assert_eq!(curve.cadence_at(5.0), Some(2.0));
+        assert_eq!(curve.cadence_at(15.0), Some(2.5));
+        assert_eq!(curve.cadence_at(21.0), None);
+        assert_eq!(curve.jump_count(15.0), 20.0 + 11.25);
+
+        assert!(GroundTruth::parse("1.0\n0.5, 2.0").is_err());
+        assert!(matches!(
+            GroundTruth::parse("# jumps\n1.0\n1.5s"),
+            Err(Error::MalformedLine { line: 3, .. })
+        ));
+        assert!(GroundTruth::parse("2.0\n1.0").is_err());
+        assert!(GroundTruth::parse("1.0\n1.0\n1.5").is_err());
+        assert!(GroundTruth::parse("# nothing").is_err());
+    }
+
+    #[test]
+    fn it_evaluates_estimates() {
+        // cadence steps from 2Hz to 3Hz at 10s
+        let truth = GroundTruth::parse("0, 2\n10, 2\n10, 3\n20, 3").unwrap();
+
+        // estimate starts at 2s and follows the change a second late
+        let estimates: Vec<_> = (0..80)
+            .map(|n| {
+                let seconds = n as f32 * 0.25;
+                let estimate = if seconds < 2.0 {
+                    None
+                } else if seconds < 11.25 {
+                    Some(2.0)
+                } else {
+                    Some(3.0)
+                };
+
+                (Duration::from_secs_f32(seconds), estimate)
+            })
+            .collect();
+
+        let evaluation = Evaluation::new(&truth, &estimates);
+
+        assert!((evaluation.coverage - 0.9).abs() < 0.01);
+        assert_eq!(evaluation.tempo_changes, 1);
+        assert_eq!(evaluation.missed_tempo_changes, 0);
+        assert_eq!(evaluation.mean_lag, Some(1.0));
+        // wrong for one second out of 18
+        assert!((evaluation.mae.unwrap() - 4.0 / 72.0).abs() < 0.01);
+        // misses the first 2s at 2Hz and is 1.25s late to speed up by 1Hz
+        assert!((evaluation.count_error() - (-4.0 - 1.25)).abs() < 0.01);
+        // speeds up and doesn't slow down
+        assert!(evaluation.rhythm.drift_hz_per_minute.unwrap() > 0.0);
+        assert_eq!(evaluation.rhythm.fatigue, Some(0.0));
+    }
+}
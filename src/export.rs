@@ -0,0 +1,256 @@
+//! Export of the frequency timeline into formats spreadsheets understand.
+//!
+//! Each row is either a report of a single analyzer, the consensus or a change
+//! in activity (see [`ActivityChange`]). Reports name the index of their
+//! source, as given on the command line:
+//!
+//! ```text
+//! seconds,kind,source,window_seconds,hz,bpm
+//! 4.233,report,0,4,2.25,135
+//! 4.233,report,1,4,2.5,150
+//! 4.233,consensus,,,2.25,135
+//! 12.500,tripped,,,,
+//! ```
+//!
+//! [`ActivityChange`]: jump_rope::activity::ActivityChange
+//!
+//! JSON Lines rows have the same fields. Missing values are empty in CSV and
+//! null in JSON.
+//!
+//! Traces can also be exported as a graph of the consensus, see [`graph`].
+
+use crate::app_error::AppResult;
+use crate::graph;
+use crate::trace::{self, TraceEvent, TraceSink};
+use jump_rope::{Error, Result};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    JsonLines,
+}
+
+/// Writes the timeline rows as events happen.
+pub struct TimelineWriter<W: Write> {
+    out: W,
+    format: Format,
+    // Needed to convert window sizes from frames to seconds. Known once the
+    // start event is written.
+    frame_rate: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct Row {
+    seconds: f64,
+    kind: &'static str,
+    source: Option<usize>,
+    window_seconds: Option<f32>,
+    hz: Option<f32>,
+    bpm: Option<f32>,
+}
+
+/// Converts a recorded trace into a timeline file, or into a graph if the
+/// file is an image, see [`graph::is_image`].
+pub fn from_trace(
+    trace: impl AsRef<Path>,
+    out: impl AsRef<Path>,
+) -> AppResult<()> {
+    if graph::is_image(&out) {
+        return Ok(graph::from_trace(trace, out)?);
+    }
+
+    let mut writer = TimelineWriter::create(out)?;
+    for event in trace::read(trace)? {
+        writer.write(&event)?;
+    }
+
+    Ok(())
+}
+
+impl Format {
+    /// ".csv" files are CSV, ".json" and ".jsonl" files are JSON Lines.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let extension = path
+            .as_ref()
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("csv") => Ok(Self::Csv),
+            Some("json") | Some("jsonl") => Ok(Self::JsonLines),
+            _ => Err(Error::Unsupported(
+                "Export file must end with .csv, .json or .jsonl".to_string(),
+            )),
+        }
+    }
+}
+
+impl TimelineWriter<BufWriter<File>> {
+    /// The format is given by the file extension, see [`Format::from_path`].
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let format = Format::from_path(&path)?;
+        let out = BufWriter::new(File::create(path)?);
+
+        Self::new(out, format)
+    }
+}
+
+impl<W: Write> TimelineWriter<W> {
+    pub fn new(mut out: W, format: Format) -> Result<Self> {
+        if format == Format::Csv {
+            writeln!(out, "seconds,kind,source,window_seconds,hz,bpm")?;
+        }
+
+        Ok(Self {
+            out,
+            format,
+            frame_rate: None,
+        })
+    }
+
+    fn write_row(&mut self, row: &Row) -> Result<()> {
+        match self.format {
+            Format::Csv => {
+                fn optional(value: Option<impl ToString>) -> String {
+                    value.map(|v| v.to_string()).unwrap_or_default()
+                }
+                writeln!(
+                    self.out,
+                    "{:.3},{},{},{},{},{}",
+                    row.seconds,
+                    row.kind,
+                    optional(row.source),
+                    optional(row.window_seconds),
+                    optional(row.hz),
+                    optional(row.bpm),
+                )?;
+            }
+            Format::JsonLines => {
+                serde_json::to_writer(&mut self.out, row)?;
+                self.out.write_all(b"\n")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> TraceSink for TimelineWriter<W> {
+    fn write(&mut self, event: &TraceEvent) -> AppResult<()> {
+        let row = match event {
+            TraceEvent::Start { frame_rate } => {
+                self.frame_rate = Some(*frame_rate);
+                return Ok(());
+            }
+            TraceEvent::Report {
+                seconds,
+                window,
+                frequency,
+                source,
+                ..
+            } => Row {
+                seconds: *seconds,
+                kind: "report",
+                source: Some(*source),
+                window_seconds: self
+                    .frame_rate
+                    .map(|frame_rate| *window as f32 / frame_rate as f32),
+                hz: Some(*frequency),
+                bpm: Some(frequency * 60.0),
+            },
+            TraceEvent::Consensus { seconds, frequency } => Row {
+                seconds: *seconds,
+                kind: "consensus",
+                source: None,
+                window_seconds: None,
+                hz: *frequency,
+                bpm: frequency.map(|hz| hz * 60.0),
+            },
+            TraceEvent::Activity { seconds, change } => Row {
+                seconds: *seconds,
+                kind: change.as_str(),
+                source: None,
+                window_seconds: None,
+                hz: None,
+                bpm: None,
+            },
+        };
+
+        self.write_row(&row)?;
+
+        // see [`TraceWriter`] on why we flush
+        if row.kind == "consensus" {
+            self.out.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn export(format: Format) -> String {
+        let events = [
+            TraceEvent::Start { frame_rate: 30 },
+            TraceEvent::Report {
+                seconds: 4.25,
+                window: 120,
+                frame_index: 127,
+                frequency: 2.5,
+                confidence: 0.75,
+                source: 0,
+            },
+            // e.g. a second camera from the front
+            TraceEvent::Report {
+                seconds: 4.25,
+                window: 120,
+                frame_index: 127,
+                frequency: 2.25,
+                confidence: 0.5,
+                source: 1,
+            },
+            TraceEvent::Consensus {
+                seconds: 4.25,
+                frequency: None,
+            },
+        ];
+
+        let mut writer = TimelineWriter::new(vec![], format).unwrap();
+        for event in &events {
+            writer.write(event).unwrap();
+        }
+
+        String::from_utf8(writer.out).unwrap()
+    }
+
+    #[test]
+    fn it_exports_csv() {
+        assert_eq!(
+            export(Format::Csv),
+            "seconds,kind,source,window_seconds,hz,bpm\n\
+             4.250,report,0,4,2.5,150\n\
+             4.250,report,1,4,2.25,135\n\
+             4.250,consensus,,,,\n"
+        );
+    }
+
+    #[test]
+    fn it_exports_json_lines() {
+        assert_eq!(
+            export(Format::JsonLines),
+            "{\"seconds\":4.25,\"kind\":\"report\",\"source\":0,\
+             \"window_seconds\":4.0,\"hz\":2.5,\"bpm\":150.0}\n\
+             {\"seconds\":4.25,\"kind\":\"report\",\"source\":1,\
+             \"window_seconds\":4.0,\"hz\":2.25,\"bpm\":135.0}\n\
+             {\"seconds\":4.25,\"kind\":\"consensus\",\"source\":null,\
+             \"window_seconds\":null,\"hz\":null,\"bpm\":null}\n"
+        );
+    }
+}
@@ -1,20 +1,38 @@
 use crate::prelude::*;
-use ffmpeg::format::{context::Input, input, Pixel};
+use crate::source::{Frame, FrameSource};
+use ffmpeg::format::{
+    self, context::Input, context::Output, input, input_with_dictionary,
+    output, Pixel,
+};
 use ffmpeg::media::Type;
 use ffmpeg::software::scaling::{context::Context, flag::Flags};
+use ffmpeg::util::error::EAGAIN;
 use ffmpeg::util::frame;
-use ffmpeg::{decoder, Rational};
-use image::{GrayImage, ImageBuffer};
+use ffmpeg::{codec, decoder, encoder, Dictionary, Packet, Rational};
+use image::{GrayImage, ImageBuffer, RgbImage};
 use std::path::Path;
+use std::thread;
+use std::time::Duration;
 
+/// Decodes frames of a video file or a capture device with ffmpeg.
 pub struct FrameIter {
     ictx: Input,
     decoder: decoder::Video,
     scaler: Context,
     video_stream_index: usize,
+    frame_rate: usize,
+    // Converts frame presentation timestamps to seconds.
+    time_base: Rational,
+    // Presentation timestamp of the first frame. Devices don't start at zero.
+    first_pts: Option<i64>,
+    // How many frames have been read so far. Used for timestamps when the
+    // decoder doesn't provide any.
+    frames_read: usize,
     // This is set to true when input emits eof, so we won't attempt to fetch
     // any more packets on next iteration.
     eof: bool,
+    // Why no more frames are read before the end, e.g. the device is gone.
+    error: Option<Error>,
     // To avoid reallocation, we keep a buffer where ffmpeg loads input
     // (original) video frames.
     input_frame_buffer: frame::video::Video,
@@ -25,16 +43,45 @@ pub struct FrameIter {
 
 impl FrameIter {
     pub fn from_file(video_path: impl AsRef<Path>) -> Result<Self> {
-        let ictx = input(&video_path)?;
+        Self::from_input(input(&video_path)?)
+    }
+
+    /// Opens the V4L2 device with options of the ffmpeg input, e.g. to ask
+    /// for a capture format with `("video_size", "1280x720")`.
+    pub fn from_device(
+        device_path: impl AsRef<Path>,
+        options: &[(&str, String)],
+    ) -> Result<Self> {
+        let mut dictionary = Dictionary::new();
+        for (key, value) in options {
+            dictionary.set(key, value);
+        }
+
+        Self::from_input(input_with_dictionary(&device_path, dictionary)?)
+    }
+
+    fn from_input(ictx: Input) -> Result<Self> {
         let input = ictx
             .streams()
             .best(Type::Video)
             .ok_or(ffmpeg::Error::StreamNotFound)?;
 
         let video_stream_index = input.index();
+        let time_base = input.time_base();
 
         let decoder = input.codec().decoder().video()?;
 
+        // devices don't always report the frame rate to the decoder
+        let Rational(numerator, denominator) = decoder
+            .frame_rate()
+            .unwrap_or_else(|| input.avg_frame_rate());
+        if numerator <= 0 || denominator <= 0 {
+            return Err(Error::Unsupported(
+                "Cannot get frame rate of the video".to_string(),
+            ));
+        }
+        let frame_rate = (numerator / denominator) as usize;
+
         let scaler = Context::get(
             decoder.format(),
             decoder.width(),
@@ -50,60 +97,110 @@ impl FrameIter {
             decoder,
             scaler,
             video_stream_index,
+            frame_rate,
+            time_base,
+            first_pts: None,
+            frames_read: 0,
             eof: false,
+            error: None,
             input_frame_buffer: frame::video::Video::empty(),
             converted_frame_buffer: frame::video::Video::empty(),
         })
     }
+}
 
-    pub fn width(&self) -> u32 {
+impl FrameSource for FrameIter {
+    fn width(&self) -> u32 {
         self.decoder.width()
     }
 
-    pub fn height(&self) -> u32 {
+    fn height(&self) -> u32 {
         self.decoder.height()
     }
 
-    pub fn frame_rate(&self) -> usize {
-        let Rational(numerator, denominator) =
-            self.decoder.frame_rate().expect("Cannot get frame rate");
+    fn frame_rate(&self) -> usize {
+        self.frame_rate
+    }
 
-        (numerator / denominator) as usize
+    fn take_error(&mut self) -> Option<Error> {
+        self.error.take()
     }
 }
 
 impl Iterator for FrameIter {
-    type Item = GrayImage;
+    type Item = Frame;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self
-            .decoder
-            .receive_frame(&mut self.input_frame_buffer)
-            .is_ok()
-        {
-            self.read_input_frame()
-        } else if self.eof {
-            None
-        } else {
-            let mut packets = self.ictx.packets();
-
-            if let Some((stream, packet)) = packets.next() {
-                if stream.index() == self.video_stream_index {
-                    self.decoder.send_packet(&packet).ok()?;
-                }
-
-                self.next()
-            } else {
-                self.decoder.send_eof().ok()?;
-                self.eof = true;
-                self.next()
+        loop {
+            if self
+                .decoder
+                .receive_frame(&mut self.input_frame_buffer)
+                .is_ok()
+            {
+                return self.read_input_frame();
+            } else if self.eof || self.error.is_some() {
+                return None;
             }
+
+            self.read_packet();
         }
     }
 }
 
 impl FrameIter {
-    fn read_input_frame(&mut self) -> Option<GrayImage> {
+    // Sends the next packet of the video stream to the decoder. Unlike
+    // [`Input::packets`], which skips errors and therefore never ends once a
+    // device is unplugged, the first error stops the iteration.
+    fn read_packet(&mut self) {
+        let mut packet = Packet::empty();
+        match packet.read(&mut self.ictx) {
+            Ok(()) if packet.stream() == self.video_stream_index => {
+                if let Err(e) = self.decoder.send_packet(&packet) {
+                    self.error = Some(e.into());
+                }
+            }
+            Ok(()) => (),
+            Err(ffmpeg::Error::Eof) => {
+                if let Err(e) = self.decoder.send_eof() {
+                    self.error = Some(e.into());
+                }
+                self.eof = true;
+            }
+            // devices opened as non-blocking have no frame ready yet
+            Err(ffmpeg::Error::Other { errno }) if errno == EAGAIN => {
+                thread::sleep(Duration::from_millis(1));
+            }
+            Err(e) => {
+                self.error = Some(Error::Disconnected(e.to_string()));
+            }
+        }
+    }
+
+    fn read_input_frame(&mut self) -> Option<Frame> {
+        let timestamp = self.input_frame_timestamp();
+        self.frames_read += 1;
+
+        let image = self.convert_input_frame()?;
+        Some(Frame { timestamp, image })
+    }
+
+    // Uses the presentation timestamp of the frame relative to the first
+    // frame, or falls back to the frame rate if the decoder doesn't know.
+    fn input_frame_timestamp(&mut self) -> Duration {
+        match self.input_frame_buffer.timestamp() {
+            Some(pts) => {
+                let first_pts = *self.first_pts.get_or_insert(pts);
+                let seconds =
+                    (pts - first_pts) as f64 * f64::from(self.time_base);
+                Duration::from_secs_f64(seconds.max(0.0))
+            }
+            None => Duration::from_secs_f64(
+                self.frames_read as f64 / self.frame_rate() as f64,
+            ),
+        }
+    }
+
+    fn convert_input_frame(&mut self) -> Option<GrayImage> {
         // this function must be called after decoder loads data into this
         // buffer with "receive_frame"
         let frame = &mut self.converted_frame_buffer;
@@ -121,18 +218,152 @@ impl FrameIter {
     }
 }
 
+/// Encodes RGB frames into a video file with ffmpeg, in H.264 if available.
+pub struct VideoWriter {
+    octx: Output,
+    encoder: encoder::video::Encoder,
+    // Converts the RGB frames to the pixel format of the encoder.
+    scaler: Context,
+    // Time base of the encoder, the frames are timestamped in frames.
+    time_base: Rational,
+    // Time base of the stream in the file, which the muxer may choose.
+    stream_time_base: Rational,
+    frames_written: i64,
+    // To avoid reallocation, we keep a buffer where the RGB frames are copied
+    // to, and a buffer for the converted frames.
+    input_frame_buffer: frame::video::Video,
+    converted_frame_buffer: frame::video::Video,
+}
+
+impl VideoWriter {
+    /// The container is guessed from the file extension, e.g. ".mp4".
+    pub fn create(
+        path: impl AsRef<Path>,
+        width: u32,
+        height: u32,
+        frame_rate: usize,
+    ) -> Result<Self> {
+        let mut octx = output(&path)?;
+        let global_header =
+            octx.format().flags().contains(format::Flags::GLOBAL_HEADER);
+        let video_codec = encoder::find(codec::Id::H264)
+            .or_else(|| encoder::find(codec::Id::MPEG4))
+            .ok_or(ffmpeg::Error::EncoderNotFound)?;
+
+        // the chroma of yuv420p is subsampled, so the size must be even
+        let (out_width, out_height) = (width & !1, height & !1);
+        let time_base = Rational(1, frame_rate as i32);
+
+        let encoder = {
+            let mut stream = octx.add_stream(video_codec)?;
+            let mut encoder = stream.codec().encoder().video()?;
+            encoder.set_width(out_width);
+            encoder.set_height(out_height);
+            encoder.set_format(Pixel::YUV420P);
+            encoder.set_time_base(time_base);
+            encoder.set_frame_rate(Some(Rational(frame_rate as i32, 1)));
+            if global_header {
+                encoder.set_flags(codec::Flags::GLOBAL_HEADER);
+            }
+
+            let encoder = encoder.open_as(video_codec)?;
+            stream.set_parameters(&encoder);
+            stream.set_time_base(time_base);
+            encoder
+        };
+
+        octx.write_header()?;
+        let stream_time_base = octx
+            .stream(0)
+            .ok_or(ffmpeg::Error::StreamNotFound)?
+            .time_base();
+
+        let scaler = Context::get(
+            Pixel::RGB24,
+            width,
+            height,
+            Pixel::YUV420P,
+            out_width,
+            out_height,
+            Flags::BILINEAR,
+        )?;
+
+        Ok(Self {
+            octx,
+            encoder,
+            scaler,
+            time_base,
+            stream_time_base,
+            frames_written: 0,
+            input_frame_buffer: frame::video::Video::new(
+                Pixel::RGB24,
+                width,
+                height,
+            ),
+            converted_frame_buffer: frame::video::Video::empty(),
+        })
+    }
+
+    /// The image must be of the size the writer was created with.
+    pub fn write(&mut self, image: &RgbImage) -> Result<()> {
+        let frame = &mut self.input_frame_buffer;
+        debug_assert_eq!(image.dimensions(), (frame.width(), frame.height()));
+
+        // ffmpeg may pad the rows
+        let row_len = image.width() as usize * 3;
+        let stride = frame.stride(0);
+        let data = frame.data_mut(0);
+        for (y, row) in image.as_raw().chunks_exact(row_len).enumerate() {
+            data[y * stride..y * stride + row_len].copy_from_slice(row);
+        }
+
+        let converted = &mut self.converted_frame_buffer;
+        self.scaler.run(&self.input_frame_buffer, converted)?;
+        converted.set_pts(Some(self.frames_written));
+        self.frames_written += 1;
+
+        self.encoder.send_frame(converted)?;
+        self.write_packets()
+    }
+
+    /// Flushes the encoder and finishes the file.
+    pub fn finish(mut self) -> Result<()> {
+        self.encoder.send_eof()?;
+        self.write_packets()?;
+        self.octx.write_trailer()?;
+
+        Ok(())
+    }
+
+    fn write_packets(&mut self) -> Result<()> {
+        let mut packet = Packet::empty();
+        while self.encoder.receive_packet(&mut packet).is_ok() {
+            packet.set_stream(0);
+            packet.rescale_ts(self.time_base, self.stream_time_base);
+            packet.write_interleaved(&mut self.octx)?;
+        }
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
+    #[ignore = "needs test/assets/sample_1.mp4, which is not checked in"]
     fn it_loads_video() {
         let file = "test/assets/sample_1.mp4";
         let frames = FrameIter::from_file(file).expect("Cannot load video");
 
+        let mut last_timestamp = None;
         for frame in frames.take(100) {
-            assert_eq!(frame.width(), 1280);
-            assert_eq!(frame.height(), 720);
+            assert_eq!(frame.image.width(), 1280);
+            assert_eq!(frame.image.height(), 720);
+
+            assert!(Some(frame.timestamp) >= last_timestamp);
+            last_timestamp = Some(frame.timestamp);
         }
     }
 }
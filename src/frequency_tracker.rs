@@ -1,12 +1,98 @@
+use crate::activity::{
+    Activity, ActivityChange, ActivityDetector, ActivityEvent,
+};
 use crate::analyzer;
+use crate::prelude::*;
+use crate::rhythm::{Rhythm, RhythmStats};
+use serde::Serialize;
+use std::cmp::Ordering;
 use std::collections::BTreeMap;
 use std::sync::Mutex;
+use std::time::Duration;
 
 /// Keeps track of latest frequencies for all window sizes and exports logic
 /// to calculate the consensus.
+///
+/// The reports can come from several sources, e.g. cameras from the side and
+/// from the front, each with its own analyzers. Their consensus values are
+/// fused into one cadence, see [`FrequencyTracker::update_from`].
 pub struct FrequencyTracker {
     frame_rate: usize,
-    inner: Mutex<BTreeMap<usize, analyzer::Report>>,
+    inner: Mutex<State>,
+    // Status of each source, see [`FrequencyTracker::set_status_of`].
+    status: Mutex<BTreeMap<usize, SourceStatus>>,
+}
+
+/// Everything the tracker knows at a point in time.
+#[derive(Debug, Clone, Serialize)]
+pub struct Snapshot {
+    /// The consensus in Hz, see [`FrequencyTracker::calculate_latest`].
+    pub frequency: Option<f32>,
+    pub bpm: Option<f32>,
+    /// Confidence of the report the consensus of the most confident source
+    /// settled on, see [`analyzer::Report::confidence`].
+    pub confidence: Option<f32>,
+    /// Jumps counted since the start by integrating the consensus over time.
+    pub jump_count: f32,
+    pub activity: Activity,
+    /// Trips the athlete recovered from, see [`ActivityDetector::trips`].
+    pub trips: usize,
+    /// Steadiness of the cadence in the current or the latest set.
+    pub rhythm: RhythmStats,
+    /// Latest estimate of each window size of each source, from the smallest
+    /// window of the first source.
+    pub windows: Vec<WindowEstimate>,
+    /// See [`FrequencyTracker::status`].
+    pub source: SourceStatus,
+}
+
+/// What happens to the source of the frames, see
+/// [`FrequencyTracker::set_status`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", content = "error", rename_all = "snake_case")]
+pub enum SourceStatus {
+    /// Frames are being analysed.
+    Running,
+    /// The source was lost with given error and is being opened again.
+    Reconnecting(String),
+    /// The source reached its end.
+    Ended,
+    /// The analysis stopped with given error.
+    Failed(String),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WindowEstimate {
+    /// Index of the source, see [`FrequencyTracker::update_from`].
+    pub source: usize,
+    pub window_seconds: f32,
+    /// Timestamp of the frame the estimate was made at.
+    pub seconds: f64,
+    pub frequency: f32,
+    pub confidence: f32,
+}
+
+#[derive(Default)]
+struct State {
+    // Latest report of each window size of each source.
+    reports: BTreeMap<usize, BTreeMap<usize, analyzer::Report>>,
+    // See [`Snapshot::jump_count`].
+    jump_count: f32,
+    // Jumps have been counted up to the timestamp of this report.
+    counted_until: Option<Duration>,
+    // See [`FrequencyTracker::tick`].
+    activity: ActivityDetector,
+    // The activity has been ticked up to this time.
+    ticked_until: Duration,
+    // Consensus of the current set.
+    rhythm: Rhythm,
+}
+
+// The cadence the sources agree on, see [`FrequencyTracker::fuse`].
+#[derive(Debug, Clone, Copy)]
+struct Fused {
+    frequency: f32,
+    confidence: f32,
 }
 
 impl FrequencyTracker {
@@ -14,19 +100,236 @@ impl FrequencyTracker {
         Self {
             frame_rate,
             inner: Default::default(),
+            status: Default::default(),
         }
     }
 
+    /// Same as [`Self::update_from`] the first source.
     pub fn update(&self, report: analyzer::Report) {
+        self.update_from(0, report)
+    }
+
+    /// Applies the report of an analyzer of given source. The sources are
+    /// indexed from zero, and all of them must share the frame rate and the
+    /// timeline.
+    pub fn update_from(&self, source: usize, report: analyzer::Report) {
         let mut guard = self.inner.lock().unwrap();
-        guard.insert(report.window, report);
+        let state = &mut *guard;
+
+        // the athlete has been jumping at the latest consensus since the
+        // previous report, unless there were no reports for so long that
+        // they most likely stopped
+        if let Some(counted_until) = state.counted_until {
+            let elapsed = report.timestamp.saturating_sub(counted_until);
+            let stale = Duration::from_millis(STALE_REPORT_AFTER_MS as u64);
+            if let Some(consensus) = self.fuse(&state.reports) {
+                if elapsed <= stale {
+                    state.jump_count +=
+                        consensus.frequency * elapsed.as_secs_f32();
+                }
+            }
+        }
+        state.counted_until = Some(match state.counted_until {
+            Some(counted_until) => counted_until.max(report.timestamp),
+            None => report.timestamp,
+        });
+
+        let timestamp = report.timestamp;
+        state
+            .reports
+            .entry(source)
+            .or_default()
+            .insert(report.window, report);
+        if let Some(consensus) = self.fuse(&state.reports) {
+            state.rhythm.push(timestamp, consensus.frequency);
+        }
     }
 
+    /// The cadence the sources agree on. There's none once the reports stop
+    /// arriving, see [`Self::tick`].
     pub fn calculate_latest(&self) -> Option<f32> {
         let guard = self.inner.lock().unwrap();
+
+        self.fuse_fresh(&guard).map(|fused| fused.frequency)
+    }
+
+    /// Feeds the current consensus into the activity state machine, see
+    /// [`ActivityDetector`]. Should be called regularly even without new
+    /// reports, e.g. with each frame, as that's how stops are noticed.
+    ///
+    /// The consensus is considered lost when the freshest report is older
+    /// than one and a half seconds.
+    pub fn tick(&self, now: Duration) -> Option<ActivityEvent> {
+        let mut guard = self.inner.lock().unwrap();
+        let state = &mut *guard;
+
+        // each source ticks with its own frames, which don't arrive in order
+        let now = now.max(state.ticked_until);
+        state.ticked_until = now;
+
+        let cadence = self.fuse_fresh(state).map(|fused| fused.frequency);
+
+        let event = state.activity.update(now, cadence);
+        if let Some(event) = event {
+            if event.change == ActivityChange::Started {
+                state.rhythm.start_set(event.at);
+            }
+        }
+
+        event
+    }
+
+    pub fn activity(&self) -> Activity {
+        self.inner.lock().unwrap().activity.activity()
+    }
+
+    /// Forgets the reports of all window sizes of given source, e.g. when
+    /// its analyzers are replaced after it reconnects. The jump count and the
+    /// activity carry on.
+    pub fn reset_reports(&self, source: usize) {
+        self.inner.lock().unwrap().reports.remove(&source);
+    }
+
+    /// Same as [`Self::set_status_of`] the first source.
+    pub fn set_status(&self, status: SourceStatus) {
+        self.set_status_of(0, status)
+    }
+
+    /// Published by whoever reads the frames of given source, so that the UI
+    /// and the clients of the server can show why the cadence doesn't
+    /// update.
+    pub fn set_status_of(&self, source: usize, status: SourceStatus) {
+        self.status.lock().unwrap().insert(source, status);
+    }
+
+    /// The status of the first source which isn't running, if any.
+    pub fn status(&self) -> SourceStatus {
+        self.status
+            .lock()
+            .unwrap()
+            .values()
+            .find(|status| **status != SourceStatus::Running)
+            .cloned()
+            .unwrap_or(SourceStatus::Running)
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        let guard = self.inner.lock().unwrap();
+        let fused = self.fuse_fresh(&guard);
+
+        Snapshot {
+            frequency: fused.map(|fused| fused.frequency),
+            bpm: fused.map(|fused| fused.frequency * 60.0),
+            confidence: fused.map(|fused| fused.confidence),
+            jump_count: guard.jump_count,
+            activity: guard.activity.activity(),
+            trips: guard.activity.trips(),
+            rhythm: guard.rhythm.stats(),
+            windows: guard
+                .reports
+                .iter()
+                .flat_map(|(source, reports)| {
+                    reports.values().map(move |report| (*source, report))
+                })
+                .map(|(source, report)| WindowEstimate {
+                    source,
+                    window_seconds: report.window as f32
+                        / self.frame_rate as f32,
+                    seconds: report.timestamp.as_secs_f64(),
+                    frequency: report.frequency,
+                    confidence: report.confidence,
+                })
+                .collect(),
+            source: self.status(),
+        }
+    }
+
+    // The consensus, unless the freshest report is older than
+    // [`STALE_REPORT_AFTER_MS`] at the latest tick, i.e. the reports stopped
+    // arriving, most likely because the athlete stopped jumping.
+    fn fuse_fresh(&self, state: &State) -> Option<Fused> {
+        let stale = Duration::from_millis(STALE_REPORT_AFTER_MS as u64);
+        let fresh = state
+            .reports
+            .values()
+            .flat_map(|reports| reports.values())
+            .any(|report| {
+                state.ticked_until.saturating_sub(report.timestamp) <= stale
+            });
+
+        self.fuse(&state.reports).filter(|_| fresh)
+    }
+
+    // Combines the consensus of each source into one cadence. The most
+    // confident source leads, and the sources which agree with it within the
+    // sensitivity of its window are averaged in, weighted by their
+    // confidence. Sources which disagree most likely lost sight of the rope,
+    // e.g. the athlete turned away from the camera, so they are left out.
+    //
+    // So are the sources whose reports stopped arriving, i.e. their freshest
+    // report is older than [`STALE_REPORT_AFTER_MS`] compared to the freshest
+    // report of any source.
+    fn fuse(
+        &self,
+        sources: &BTreeMap<usize, BTreeMap<usize, analyzer::Report>>,
+    ) -> Option<Fused> {
+        let freshest = |reports: &BTreeMap<usize, analyzer::Report>| {
+            reports.values().map(|report| report.timestamp).max()
+        };
+        let now = sources.values().filter_map(freshest).max()?;
+        let stale = Duration::from_millis(STALE_REPORT_AFTER_MS as u64);
+
+        let estimates: Vec<_> = sources
+            .values()
+            .filter(|reports| {
+                matches!(
+                    freshest(reports),
+                    Some(timestamp) if now.saturating_sub(timestamp) <= stale
+                )
+            })
+            .filter_map(|reports| self.consensus(reports))
+            .collect();
+        // the first source wins a tie
+        let leader = estimates.iter().rev().max_by(|a, b| {
+            a.confidence
+                .partial_cmp(&b.confidence)
+                .unwrap_or(Ordering::Equal)
+        })?;
+
+        // same sensitivity as within the consensus of a single source
+        let s = self.frame_rate as f32 / leader.window as f32;
+        let agreeing: Vec<_> = estimates
+            .iter()
+            .filter(|report| (report.frequency - leader.frequency).abs() < s)
+            .collect();
+        let total_confidence: f32 =
+            agreeing.iter().map(|report| report.confidence).sum();
+
+        // a single source is taken as it is, so is a trace recorded before
+        // confidence was reported
+        let frequency = if agreeing.len() == 1 || total_confidence <= 0.0 {
+            leader.frequency
+        } else {
+            agreeing
+                .iter()
+                .map(|report| report.frequency * report.confidence)
+                .sum::<f32>()
+                / total_confidence
+        };
+
+        Some(Fused {
+            frequency,
+            confidence: leader.confidence,
+        })
+    }
+
+    // Returns the report whose frequency the consensus settled on.
+    fn consensus<'a>(
+        &self,
+        reports: &'a BTreeMap<usize, analyzer::Report>,
+    ) -> Option<&'a analyzer::Report> {
         let frequencies_ordered_by_window_size: Vec<_> =
-            (*guard).values().cloned().collect();
-        drop(guard);
+            reports.values().collect();
 
         // we address the compromise where higher window size reports more
         // granular frequencies but takes longer to adjust to tempo changes:
@@ -57,6 +360,157 @@ impl FrequencyTracker {
                 interval.contains(&curr.frequency)
             })
             .last()
-            .map(|report| report[1].frequency)
+            .map(|report| report[1])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{Pipeline, PipelineConfig};
+    use crate::source::CadenceScene;
+    use std::sync::Arc;
+
+    #[test]
+    fn it_follows_cadence_change() {
+        let (frame_rate, width, height) = (30, 120, 90);
+        let change_at = Duration::from_secs(12);
+        let scene =
+            CadenceScene::new(move |t| if t < change_at { 2.0 } else { 3.0 })
+                .with_noise(10);
+
+        // (timestamp, estimate) after each report
+        let mut estimates = vec![];
+        let frames = scene
+            .into_source(width, height, frame_rate)
+            .with_duration(Duration::from_secs(30));
+        let config = PipelineConfig::default();
+        let mut pipeline = Pipeline::from_source(&frames, &config).unwrap();
+        for frame in frames {
+            let timestamp = frame.timestamp;
+            for _ in pipeline.push_frame(Arc::new(frame)).unwrap() {
+                let estimate = pipeline.tracker().calculate_latest();
+                estimates.push((timestamp, estimate));
+            }
+        }
+
+        let last_estimate_before = |t: Duration| {
+            estimates
+                .iter()
+                .rev()
+                .find(|(timestamp, _)| *timestamp < t)
+                .and_then(|(_, estimate)| *estimate)
+                .expect("No consensus")
+        };
+
+        // the estimates are allowed to be off by about a frequency bin
+        let tolerance = 0.15;
+        let before_change = last_estimate_before(change_at);
+        assert!((before_change - 2.0).abs() < tolerance, "{}", before_change);
+        let after_change = last_estimate_before(Duration::from_secs(30));
+        assert!((after_change - 3.0).abs() < tolerance, "{}", after_change);
+    }
+
+    #[test]
+    fn it_counts_jumps() {
+        let frame_rate = 30;
+        let tracker = FrequencyTracker::new(frame_rate);
+        let report = |window: usize, seconds: u64| analyzer::Report {
+            window,
+            frame_index: seconds as usize * frame_rate,
+            timestamp: Duration::from_secs(seconds),
+            frequency: 2.0,
+            confidence: 0.8,
+        };
+
+        // no consensus until there are reports of two window sizes
+        tracker.update(report(120, 4));
+        tracker.update(report(120, 5));
+        assert_eq!(tracker.snapshot().jump_count, 0.0);
+
+        tracker.update(report(240, 8));
+        for seconds in 9..=18 {
+            tracker.update(report(120, seconds));
+            tracker.update(report(240, seconds));
+        }
+
+        let snapshot = tracker.snapshot();
+        assert_eq!(snapshot.frequency, Some(2.0));
+        assert_eq!(snapshot.bpm, Some(120.0));
+        assert_eq!(snapshot.confidence, Some(0.8));
+        assert_eq!(snapshot.jump_count, 20.0);
+        assert_eq!(snapshot.windows.len(), 2);
+        assert_eq!(snapshot.windows[1].window_seconds, 8.0);
+    }
+
+    #[test]
+    fn it_forgets_reports() {
+        let tracker = FrequencyTracker::new(30);
+        let report = |window: usize, seconds: u64| analyzer::Report {
+            window,
+            frame_index: seconds as usize * 30,
+            timestamp: Duration::from_secs(seconds),
+            frequency: 2.0,
+            confidence: 0.8,
+        };
+        for seconds in 8..=10 {
+            tracker.update(report(120, seconds));
+            tracker.update(report(240, seconds));
+        }
+
+        // the consensus is lost once the reports stop arriving
+        tracker.tick(Duration::from_secs(11));
+        assert_eq!(tracker.calculate_latest(), Some(2.0));
+        tracker.tick(Duration::from_secs(12));
+        assert_eq!(tracker.calculate_latest(), None);
+
+        tracker.set_status(SourceStatus::Reconnecting("gone".to_string()));
+        tracker.reset_reports(0);
+        let snapshot = tracker.snapshot();
+        assert_eq!(snapshot.frequency, None);
+        assert_eq!(snapshot.jump_count, 4.0);
+        assert!(snapshot.windows.is_empty());
+        assert_eq!(
+            serde_json::to_string(&snapshot.source).unwrap(),
+            r#"{"state":"reconnecting","error":"gone"}"#
+        );
+    }
+
+    #[test]
+    fn it_fuses_sources() {
+        let frame_rate = 30;
+        let tracker = FrequencyTracker::new(frame_rate);
+        let report =
+            |seconds: u64, window, frequency, confidence| analyzer::Report {
+                window,
+                frame_index: seconds as usize * frame_rate,
+                timestamp: Duration::from_secs(seconds),
+                frequency,
+                confidence,
+            };
+
+        // the side camera sees the rope well, the front camera barely does
+        let (front, side) = (0, 1);
+        tracker.update_from(front, report(10, 120, 2.1, 0.2));
+        tracker.update_from(front, report(10, 240, 2.1, 0.2));
+        tracker.update_from(side, report(10, 120, 2.2, 0.6));
+        tracker.update_from(side, report(10, 240, 2.2, 0.6));
+        let fused = tracker.calculate_latest().unwrap();
+        assert!((fused - 2.175).abs() < 1e-5, "{}", fused);
+        assert_eq!(tracker.snapshot().confidence, Some(0.6));
+        assert_eq!(tracker.snapshot().windows.len(), 4);
+
+        // the front camera tracks something else than the rope
+        tracker.update_from(front, report(11, 120, 0.9, 0.3));
+        tracker.update_from(front, report(11, 240, 0.9, 0.3));
+        assert_eq!(tracker.calculate_latest(), Some(2.2));
+
+        // the side camera doesn't report anymore
+        tracker.update_from(front, report(20, 120, 2.0, 0.1));
+        tracker.update_from(front, report(20, 240, 2.0, 0.1));
+        assert_eq!(tracker.calculate_latest(), Some(2.0));
+
+        tracker.set_status_of(side, SourceStatus::Ended);
+        assert_eq!(tracker.status(), SourceStatus::Ended);
     }
 }
@@ -0,0 +1,143 @@
+//! A tiny stroke font for the labels and the readout of the graph, and the
+//! annotated video, so that we don't have to ship a font file.
+//!
+//! Each glyph is a few polylines on a grid 4 units wide and 6 units tall,
+//! with the baseline at 0 and descenders below it. Only the characters which
+//! we need are supported.
+
+/// Space between glyphs, in grid units.
+const SPACING: f32 = 2.0;
+
+const GLYPH_WIDTH: f32 = 4.0;
+
+const GLYPH_HEIGHT: f32 = 6.0;
+
+type Polyline = &'static [(f32, f32)];
+
+/// Polylines which draw given text, the bottom left corner being at the
+/// origin. Unsupported characters are left blank.
+pub fn polylines(text: &str, height: f32) -> Vec<Vec<(f32, f32)>> {
+    let scale = height / GLYPH_HEIGHT;
+
+    text.chars()
+        .enumerate()
+        .flat_map(|(index, c)| {
+            let offset = index as f32 * (GLYPH_WIDTH + SPACING);
+            strokes(c).iter().map(move |polyline| {
+                polyline
+                    .iter()
+                    .map(|(x, y)| ((offset + x) * scale, y * scale))
+                    .collect()
+            })
+        })
+        .collect()
+}
+
+/// How wide given text is when drawn with [`polylines`].
+pub fn width(text: &str, height: f32) -> f32 {
+    let count = text.chars().count() as f32;
+    let scale = height / GLYPH_HEIGHT;
+
+    (count * (GLYPH_WIDTH + SPACING) - SPACING).max(0.0) * scale
+}
+
+// The glyphs are easier to read as a table.
+#[rustfmt::skip]
+fn strokes(c: char) -> &'static [Polyline] {
+    match c {
+        '0' => &[&[(0., 0.), (4., 0.), (4., 6.), (0., 6.), (0., 0.)]],
+        '1' => &[&[(1., 5.), (2., 6.), (2., 0.)], &[(1., 0.), (3., 0.)]],
+        '2' => &[&[(0., 6.), (4., 6.), (4., 3.), (0., 3.), (0., 0.),
+                   (4., 0.)]],
+        '3' => &[&[(0., 6.), (4., 6.), (4., 0.), (0., 0.)],
+                 &[(1., 3.), (4., 3.)]],
+        '4' => &[&[(0., 6.), (0., 3.), (4., 3.)], &[(4., 6.), (4., 0.)]],
+        '5' => &[&[(4., 6.), (0., 6.), (0., 3.), (4., 3.), (4., 0.),
+                   (0., 0.)]],
+        '6' => &[&[(4., 6.), (0., 6.), (0., 0.), (4., 0.), (4., 3.),
+                   (0., 3.)]],
+        '7' => &[&[(0., 6.), (4., 6.), (1., 0.)]],
+        '8' => &[&[(0., 0.), (4., 0.), (4., 6.), (0., 6.), (0., 0.)],
+                 &[(0., 3.), (4., 3.)]],
+        '9' => &[&[(4., 3.), (0., 3.), (0., 6.), (4., 6.), (4., 0.),
+                   (0., 0.)]],
+        '.' => &[&[(1.5, 0.), (2.5, 0.), (2.5, 1.), (1.5, 1.), (1.5, 0.)]],
+        '-' => &[&[(1., 3.), (3., 3.)]],
+        ',' => &[&[(2.5, 1.), (2.5, 0.), (1.5, -1.)]],
+        ':' => &[&[(2., 1.), (2., 1.5)], &[(2., 3.5), (2., 4.)]],
+        '/' => &[&[(0., 0.), (4., 6.)]],
+        '%' => &[&[(0., 0.), (4., 6.)], &[(0., 6.), (0., 5.)],
+                 &[(4., 1.), (4., 0.)]],
+        'B' => &[&[(0., 0.), (0., 6.), (3., 6.), (4., 5.), (4., 4.),
+                   (3., 3.)],
+                 &[(0., 3.), (3., 3.), (4., 2.), (4., 1.), (3., 0.),
+                   (0., 0.)]],
+        'H' => &[&[(0., 0.), (0., 6.)], &[(4., 0.), (4., 6.)],
+                 &[(0., 3.), (4., 3.)]],
+        'I' => &[&[(1., 0.), (3., 0.)], &[(2., 0.), (2., 6.)],
+                 &[(1., 6.), (3., 6.)]],
+        'M' => &[&[(0., 0.), (0., 6.), (2., 3.), (4., 6.), (4., 0.)]],
+        'P' => &[&[(0., 0.), (0., 6.), (3., 6.), (4., 5.), (4., 4.),
+                   (3., 3.), (0., 3.)]],
+        'R' => &[&[(0., 0.), (0., 6.), (3., 6.), (4., 5.), (4., 4.),
+                   (3., 3.), (0., 3.)],
+                 &[(2., 3.), (4., 0.)]],
+        'W' => &[&[(0., 6.), (1., 0.), (2., 4.), (3., 0.), (4., 6.)]],
+        'a' => &[&[(0., 4.), (4., 4.), (4., 0.), (0., 0.), (0., 2.),
+                   (4., 2.)]],
+        'b' => &[&[(0., 6.), (0., 0.), (4., 0.), (4., 4.), (0., 4.)]],
+        'c' => &[&[(4., 4.), (0., 4.), (0., 0.), (4., 0.)]],
+        'd' => &[&[(4., 0.), (4., 6.)],
+                 &[(4., 4.), (0., 4.), (0., 0.), (4., 0.)]],
+        'e' => &[&[(0., 2.), (4., 2.), (4., 4.), (0., 4.), (0., 0.),
+                   (4., 0.)]],
+        'f' => &[&[(4., 6.), (2., 6.), (1., 5.), (1., 0.)],
+                 &[(0., 4.), (3., 4.)]],
+        'g' => &[&[(4., 0.), (0., 0.), (0., 4.), (4., 4.), (4., -2.),
+                   (0., -2.)]],
+        'h' => &[&[(0., 0.), (0., 6.)],
+                 &[(0., 3.), (1., 4.), (4., 4.), (4., 0.)]],
+        'i' => &[&[(2., 0.), (2., 4.)], &[(2., 5.), (2., 5.5)]],
+        'j' => &[&[(3., 4.), (3., -1.), (2., -2.), (0., -2.)],
+                 &[(3., 5.), (3., 5.5)]],
+        'k' => &[&[(0., 0.), (0., 6.)], &[(4., 4.), (0., 2.), (4., 0.)]],
+        'l' => &[&[(2., 6.), (2., 0.)]],
+        'm' => &[&[(0., 0.), (0., 4.), (4., 4.), (4., 0.)],
+                 &[(2., 4.), (2., 0.)]],
+        'n' => &[&[(0., 0.), (0., 4.)],
+                 &[(0., 3.), (1., 4.), (4., 4.), (4., 0.)]],
+        'o' => &[&[(0., 0.), (4., 0.), (4., 4.), (0., 4.), (0., 0.)]],
+        'p' => &[&[(0., -2.), (0., 4.), (4., 4.), (4., 1.), (0., 1.)]],
+        'r' => &[&[(0., 0.), (0., 4.)], &[(0., 3.), (1., 4.), (4., 4.)]],
+        's' => &[&[(4., 4.), (0., 4.), (0., 2.), (4., 2.), (4., 0.),
+                   (0., 0.)]],
+        't' => &[&[(2., 6.), (2., 0.), (4., 0.)], &[(0., 4.), (4., 4.)]],
+        'u' => &[&[(0., 4.), (0., 0.), (4., 0.), (4., 4.)]],
+        'v' => &[&[(0., 4.), (2., 0.), (4., 4.)]],
+        'w' => &[&[(0., 4.), (1., 0.), (2., 3.), (3., 0.), (4., 4.)]],
+        'z' => &[&[(0., 4.), (4., 4.), (0., 0.), (4., 0.)]],
+        _ => &[],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_lays_out_text() {
+        assert_eq!(width("", 12.0), 0.0);
+        assert_eq!(width("1", 12.0), 8.0);
+        assert_eq!(width("150 BPM", 12.0), 80.0);
+
+        // the space is blank but moves the following glyphs
+        let lines = polylines("1 H", 12.0);
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0][0], (2.0, 10.0));
+        assert_eq!(lines[2], vec![(24.0, 0.0), (24.0, 12.0)]);
+
+        // the workout guide
+        let text = "Interval Rest before Workout finished, 0:59 left 100%/";
+        assert!(text.chars().all(|c| c == ' ' || !strokes(c).is_empty()));
+    }
+}
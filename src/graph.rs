@@ -0,0 +1,408 @@
+//! The cadence graph of a whole session as an image to paste into reports,
+//! either as SVG or rasterised to PNG.
+//!
+//! The curve is a lyon path with x in seconds and y in Hz. The live view
+//! exports the curve it draws, and the `export` command a curve through the
+//! consensus of a recorded trace, see [`curve`]. The axes are labelled in Hz,
+//! BPM and seconds like in the live view.
+
+use crate::glyphs;
+use crate::raster::{draw_line, draw_text};
+use crate::trace::{self, TraceEvent};
+use image::{Rgb, RgbImage};
+use jump_rope::{Error, Result, HIGHEST_FREQUENCY_OF_INTEREST};
+use lyon_tessellation::geom::euclid::default::Transform2D;
+use lyon_tessellation::math::{point, vector, Point};
+use lyon_tessellation::path::{self as lyon, iterator::PathIterator};
+use lyon_tessellation::path::{Event, PathEvent};
+use std::fs;
+use std::path::Path;
+
+const WIDTH: u32 = 1200;
+
+const HEIGHT: u32 = 400;
+
+/// Space around the plot in px, the left one is for the y axis labels and the
+/// bottom one for the time axis labels.
+const MARGIN_LEFT: f32 = 220.0;
+const MARGIN_RIGHT: f32 = 30.0;
+const MARGIN_TOP: f32 = 20.0;
+const MARGIN_BOTTOM: f32 = 50.0;
+
+/// How many Hz apart the ticks on the y axis are.
+const HZ_PER_TICK: f32 = 0.5;
+
+/// The time axis has the fewest ticks of these intervals in seconds which
+/// keeps the ticks at most [`MAX_TIME_TICKS`].
+const SECONDS_PER_TICK: &[usize] = &[5, 10, 15, 30, 60, 120, 300, 600, 1800];
+
+const MAX_TIME_TICKS: usize = 12;
+
+const LABEL_HEIGHT: f32 = 14.0;
+
+const CURVE_WIDTH: f32 = 2.0;
+
+const GRID_COLOR: Rgb<u8> = Rgb([230, 230, 230]);
+
+const AXIS_COLOR: Rgb<u8> = Rgb([128, 128, 128]);
+
+const LABEL_COLOR: Rgb<u8> = Rgb([77, 77, 77]);
+
+const CURVE_COLOR: Rgb<u8> = Rgb([0, 0, 0]);
+
+/// What the graph is made of, in px from the top left corner.
+enum Mark {
+    Line {
+        from: Point,
+        to: Point,
+        color: Rgb<u8>,
+    },
+    /// The point is on the baseline, where the text is anchored.
+    Text {
+        at: Point,
+        text: String,
+        anchor: Anchor,
+    },
+    Curve(lyon::Path),
+}
+
+#[derive(Clone, Copy)]
+enum Anchor {
+    Middle,
+    End,
+}
+
+/// A curve through the samples of `(seconds, hz)`, broken where there's no
+/// cadence.
+pub fn curve(samples: &[(f32, Option<f32>)]) -> lyon::Path {
+    let mut builder = lyon::Path::builder();
+    let mut drawing = false;
+    for (seconds, hz) in samples {
+        match hz {
+            Some(hz) if drawing => {
+                builder.line_to(point(*seconds, *hz));
+            }
+            Some(hz) => {
+                builder.begin(point(*seconds, *hz));
+                drawing = true;
+            }
+            None if drawing => {
+                builder.end(false);
+                drawing = false;
+            }
+            None => (),
+        }
+    }
+    if drawing {
+        builder.end(false);
+    }
+
+    builder.build()
+}
+
+/// Whether the file is an image which [`save`] can write.
+pub fn is_image(path: impl AsRef<Path>) -> bool {
+    matches!(extension(path).as_deref(), Some("svg") | Some("png"))
+}
+
+/// Writes the graph of the curve into a ".svg" or ".png" file.
+pub fn save(curve: &lyon::Path, path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    match extension(path).as_deref() {
+        Some("svg") => fs::write(path, svg(curve))?,
+        Some("png") => png(curve).save(path)?,
+        _ => {
+            return Err(Error::Unsupported(
+                "Graph file must end with .svg or .png".to_string(),
+            ))
+        }
+    }
+
+    Ok(())
+}
+
+/// Draws the graph of the consensus in the recorded trace.
+pub fn from_trace(
+    trace: impl AsRef<Path>,
+    out: impl AsRef<Path>,
+) -> Result<()> {
+    let samples: Vec<_> = trace::read(trace)?
+        .into_iter()
+        .filter_map(|event| match event {
+            TraceEvent::Consensus { seconds, frequency } => {
+                Some((seconds as f32, frequency))
+            }
+            _ => None,
+        })
+        .collect();
+
+    save(&curve(&samples), out)
+}
+
+pub fn svg(curve: &lyon::Path) -> String {
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" \
+         height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n\
+         <rect width=\"{w}\" height=\"{h}\" fill=\"white\"/>\n",
+        w = WIDTH,
+        h = HEIGHT,
+    );
+
+    for mark in marks(curve) {
+        let line = match mark {
+            Mark::Line { from, to, color } => format!(
+                "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" \
+                 stroke=\"{}\"/>",
+                from.x,
+                from.y,
+                to.x,
+                to.y,
+                svg_color(color),
+            ),
+            Mark::Text { at, text, anchor } => format!(
+                "<text x=\"{:.1}\" y=\"{:.1}\" font-family=\"sans-serif\" \
+                 font-size=\"{}\" fill=\"{}\" text-anchor=\"{}\">{}</text>",
+                at.x,
+                at.y,
+                LABEL_HEIGHT,
+                svg_color(LABEL_COLOR),
+                anchor.as_svg(),
+                text,
+            ),
+            Mark::Curve(curve) => format!(
+                "<path d=\"{}\" fill=\"none\" stroke=\"{}\" \
+                 stroke-width=\"{}\" stroke-linejoin=\"round\"/>",
+                svg_path(&curve),
+                svg_color(CURVE_COLOR),
+                CURVE_WIDTH,
+            ),
+        };
+        svg.push_str(&line);
+        svg.push('\n');
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+pub fn png(curve: &lyon::Path) -> RgbImage {
+    let mut image = RgbImage::from_pixel(WIDTH, HEIGHT, Rgb([255, 255, 255]));
+
+    for mark in marks(curve) {
+        match mark {
+            Mark::Line { from, to, color } => draw_line(
+                &mut image,
+                (from.x, from.y),
+                (to.x, to.y),
+                1.0,
+                color,
+            ),
+            Mark::Text { at, text, anchor } => {
+                let width = glyphs::width(&text, LABEL_HEIGHT);
+                let x = match anchor {
+                    Anchor::Middle => at.x - width / 2.0,
+                    Anchor::End => at.x - width,
+                };
+                draw_text(
+                    &mut image,
+                    &text,
+                    (x, at.y),
+                    LABEL_HEIGHT,
+                    LABEL_COLOR,
+                );
+            }
+            Mark::Curve(curve) => {
+                // the curve is in px, so this is a quarter px precision
+                for event in curve.iter().flattened(0.25) {
+                    if let PathEvent::Line { from, to } = event {
+                        draw_line(
+                            &mut image,
+                            (from.x, from.y),
+                            (to.x, to.y),
+                            CURVE_WIDTH,
+                            CURVE_COLOR,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    image
+}
+
+// Lays out the grid, the axes with their labels and the curve.
+fn marks(curve: &lyon::Path) -> Vec<Mark> {
+    // the curve spans at least a tick so that the axis isn't empty
+    let duration = curve
+        .iter()
+        .filter_map(|event| match event {
+            Event::End { .. } => None,
+            Event::Begin { at } => Some(at.x),
+            Event::Line { to, .. }
+            | Event::Quadratic { to, .. }
+            | Event::Cubic { to, .. } => Some(to.x),
+        })
+        .fold(0.0, f32::max);
+    let seconds_per_tick = seconds_per_tick(duration);
+    let duration = duration.max(seconds_per_tick as f32);
+
+    // from seconds and Hz to px, with the y axis pointing down
+    let left = MARGIN_LEFT;
+    let bottom = HEIGHT as f32 - MARGIN_BOTTOM;
+    let px_per_second = (WIDTH as f32 - MARGIN_LEFT - MARGIN_RIGHT) / duration;
+    let px_per_hz = (HEIGHT as f32 - MARGIN_TOP - MARGIN_BOTTOM)
+        / HIGHEST_FREQUENCY_OF_INTEREST;
+    let to_px = Transform2D::scale(px_per_second, -px_per_hz)
+        .then_translate(vector(left, bottom));
+    let right = left + duration * px_per_second;
+    let top = bottom - HIGHEST_FREQUENCY_OF_INTEREST * px_per_hz;
+
+    let mut marks = vec![];
+
+    // e.g. "2.5 Hz 150 BPM" left of the horizontal grid lines
+    let ticks_count = (HIGHEST_FREQUENCY_OF_INTEREST / HZ_PER_TICK) as usize;
+    for tick in 1..=ticks_count {
+        let hz = tick as f32 * HZ_PER_TICK;
+        let y = to_px.transform_point(point(0.0, hz)).y;
+        marks.push(Mark::Line {
+            from: point(left, y),
+            to: point(right, y),
+            color: GRID_COLOR,
+        });
+        marks.push(Mark::Text {
+            at: point(left - 10.0, y + LABEL_HEIGHT / 2.0),
+            text: format!("{:.1} Hz {:>3.0} BPM", hz, hz * 60.0),
+            anchor: Anchor::End,
+        });
+    }
+
+    // e.g. "30s" under the vertical grid lines
+    let ticks_count = duration as usize / seconds_per_tick;
+    for tick in 0..=ticks_count {
+        let seconds = tick * seconds_per_tick;
+        let x = to_px.transform_point(point(seconds as f32, 0.0)).x;
+        marks.push(Mark::Line {
+            from: point(x, top),
+            to: point(x, bottom),
+            color: GRID_COLOR,
+        });
+        marks.push(Mark::Text {
+            at: point(x, bottom + 10.0 + LABEL_HEIGHT),
+            text: format!("{}s", seconds),
+            anchor: Anchor::Middle,
+        });
+    }
+
+    // the axes over the grid
+    marks.push(Mark::Line {
+        from: point(left, top),
+        to: point(left, bottom),
+        color: AXIS_COLOR,
+    });
+    marks.push(Mark::Line {
+        from: point(left, bottom),
+        to: point(right, bottom),
+        color: AXIS_COLOR,
+    });
+
+    marks.push(Mark::Curve(curve.clone().transformed(&to_px)));
+    marks
+}
+
+fn seconds_per_tick(duration: f32) -> usize {
+    SECONDS_PER_TICK
+        .iter()
+        .copied()
+        .find(|seconds| duration / *seconds as f32 <= MAX_TIME_TICKS as f32)
+        .unwrap_or(SECONDS_PER_TICK[SECONDS_PER_TICK.len() - 1])
+}
+
+// The curve as the "d" attribute of an SVG path.
+fn svg_path(curve: &lyon::Path) -> String {
+    let mut d = vec![];
+    for event in curve.iter() {
+        match event {
+            Event::Begin { at } => d.push(format!("M{:.1},{:.1}", at.x, at.y)),
+            Event::Line { to, .. } => {
+                d.push(format!("L{:.1},{:.1}", to.x, to.y))
+            }
+            Event::Quadratic { ctrl, to, .. } => d.push(format!(
+                "Q{:.1},{:.1} {:.1},{:.1}",
+                ctrl.x, ctrl.y, to.x, to.y
+            )),
+            Event::Cubic {
+                ctrl1, ctrl2, to, ..
+            } => d.push(format!(
+                "C{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}",
+                ctrl1.x, ctrl1.y, ctrl2.x, ctrl2.y, to.x, to.y
+            )),
+            Event::End { close: true, .. } => d.push("Z".to_string()),
+            Event::End { .. } => (),
+        }
+    }
+
+    d.join(" ")
+}
+
+fn svg_color(color: Rgb<u8>) -> String {
+    let [r, g, b] = color.0;
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn extension(path: impl AsRef<Path>) -> Option<String> {
+    path.as_ref()
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+}
+
+impl Anchor {
+    fn as_svg(self) -> &'static str {
+        match self {
+            Self::Middle => "middle",
+            Self::End => "end",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_draws_graph() {
+        let samples = [
+            (0.0, None),
+            (10.0, Some(2.0)),
+            (20.0, Some(2.5)),
+            (30.0, None),
+            (40.0, Some(3.0)),
+            (50.0, Some(3.0)),
+        ];
+        let curve = curve(&samples);
+        assert_eq!(curve.iter().count(), 6);
+        assert_eq!(seconds_per_tick(50.0), 5);
+        assert_eq!(seconds_per_tick(3600.0), 300);
+
+        // 8 Hz ticks and 11 time ticks, each with a grid line and a label
+        let svg = svg(&curve);
+        assert_eq!(svg.matches("<text").count(), 8 + 11);
+        assert!(svg.contains("<path d=\"M410.0,185.0 L600.0,143.8 M"));
+        assert!(svg.contains(">2.5 Hz 150 BPM</text>"));
+        assert!(svg.contains(">50s</text>"));
+
+        // the curve ends at the right edge of the plot at 3 Hz
+        let image = png(&curve);
+        let plot_height = HEIGHT as f32 - MARGIN_TOP - MARGIN_BOTTOM;
+        let y = HEIGHT as f32 - MARGIN_BOTTOM - plot_height * 3.0 / 4.0;
+        let x = WIDTH as f32 - MARGIN_RIGHT - 5.0;
+        assert_eq!(image.get_pixel(x as u32, y as u32), &CURVE_COLOR);
+        // there's no cadence between 20 and 40 seconds
+        let x = MARGIN_LEFT + (WIDTH as f32 - MARGIN_LEFT - MARGIN_RIGHT) * 0.6;
+        let cadence = (0..HEIGHT)
+            .filter(|y| image.get_pixel(x as u32, *y) == &CURVE_COLOR)
+            .count();
+        assert_eq!(cadence, 0);
+    }
+}
@@ -0,0 +1,548 @@
+//! Sessions of each athlete stored in a local SQLite database, so that they
+//! can be compared and progress followed over weeks.
+//!
+//! A session is recorded live as a [`TraceSink`]. The database is kept up to
+//! date with each consensus, because the process exits when the window is
+//! closed.
+
+use crate::app_error::AppResult;
+use crate::cli::HistoryCommand;
+use crate::trace::{TraceEvent, TraceSink};
+use jump_rope::Error;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS athletes (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE,
+        weight_kg REAL
+    );
+    CREATE TABLE IF NOT EXISTS sessions (
+        id INTEGER PRIMARY KEY,
+        athlete_id INTEGER NOT NULL REFERENCES athletes (id),
+        source TEXT NOT NULL,
+        -- unix timestamps in seconds
+        started_at INTEGER NOT NULL,
+        ended_at INTEGER NOT NULL,
+        jump_count REAL NOT NULL DEFAULT 0
+    );
+    CREATE TABLE IF NOT EXISTS timeline (
+        session_id INTEGER NOT NULL REFERENCES sessions (id),
+        -- since the start of the video
+        seconds REAL NOT NULL,
+        -- consensus, null if there was none
+        frequency REAL
+    );
+    CREATE INDEX IF NOT EXISTS timeline_session ON timeline (session_id);
+    CREATE TABLE IF NOT EXISTS activity (
+        session_id INTEGER NOT NULL REFERENCES sessions (id),
+        seconds REAL NOT NULL,
+        -- see ActivityChange::as_str
+        change TEXT NOT NULL
+    );
+";
+
+/// Database of all sessions.
+pub struct History {
+    conn: Connection,
+}
+
+/// What's known about a finished or an ongoing session.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionSummary {
+    pub id: i64,
+    pub athlete: String,
+    pub source: String,
+    pub started_at: i64,
+    pub ended_at: i64,
+    pub jump_count: f32,
+    /// Average of the consensus while there was one.
+    pub mean_frequency: Option<f32>,
+    pub peak_frequency: Option<f32>,
+    /// Share of the session in which there was consensus.
+    pub active_ratio: f32,
+    /// How many times the athlete started jumping.
+    pub sets: usize,
+    /// Trips the athlete recovered from.
+    pub trips: usize,
+}
+
+/// Sessions of a single week, starting on Monday.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeekProgress {
+    /// Days since the unix epoch of the Monday.
+    pub monday: i64,
+    pub sessions: usize,
+    pub jump_count: f32,
+    /// Average of the session means.
+    pub mean_frequency: Option<f32>,
+}
+
+/// Records a live session, see [`TraceSink`].
+pub struct SessionRecorder {
+    history: History,
+    session_id: i64,
+    // The latest consensus and when it happened, to count the jumps.
+    latest: Option<(f64, Option<f32>)>,
+    jump_count: f32,
+}
+
+/// Runs one of the history commands and prints the result.
+pub fn run(path: Option<&Path>, command: HistoryCommand) -> AppResult<()> {
+    let history = match path {
+        Some(path) => History::open(path)?,
+        None => History::open(default_path()?)?,
+    };
+
+    match command {
+        HistoryCommand::List { athlete } => {
+            println!(
+                "{:>5} {:<12} {:<17} {:>8} {:>7} {:>9} {:>5} {:>5}",
+                "id",
+                "athlete",
+                "started",
+                "minutes",
+                "jumps",
+                "mean bpm",
+                "sets",
+                "trips"
+            );
+            for s in history.sessions(athlete.as_deref())? {
+                println!(
+                    "{:>5} {:<12} {:<17} {:>8.1} {:>7.0} {:>9} {:>5} {:>5}",
+                    s.id,
+                    s.athlete,
+                    format_timestamp(s.started_at),
+                    s.minutes(),
+                    s.jump_count,
+                    optional_bpm(s.mean_frequency),
+                    s.sets,
+                    s.trips,
+                );
+            }
+        }
+        HistoryCommand::Compare { first, second } => {
+            let no_such = |which: &str| {
+                Error::InvalidArgument(format!("No such {} session", which))
+            };
+            let a = history.session(first)?.ok_or_else(|| no_such("first"))?;
+            let b =
+                history.session(second)?.ok_or_else(|| no_such("second"))?;
+
+            let rows: &[(&str, String, String)] = &[
+                ("athlete", a.athlete.clone(), b.athlete.clone()),
+                (
+                    "started",
+                    format_timestamp(a.started_at),
+                    format_timestamp(b.started_at),
+                ),
+                (
+                    "minutes",
+                    format!("{:.1}", a.minutes()),
+                    format!("{:.1}", b.minutes()),
+                ),
+                (
+                    "jumps",
+                    format!("{:.0}", a.jump_count),
+                    format!("{:.0}", b.jump_count),
+                ),
+                (
+                    "mean bpm",
+                    optional_bpm(a.mean_frequency),
+                    optional_bpm(b.mean_frequency),
+                ),
+                (
+                    "peak bpm",
+                    optional_bpm(a.peak_frequency),
+                    optional_bpm(b.peak_frequency),
+                ),
+                (
+                    "jumping",
+                    format!("{:.0}%", a.active_ratio * 100.0),
+                    format!("{:.0}%", b.active_ratio * 100.0),
+                ),
+                ("sets", a.sets.to_string(), b.sets.to_string()),
+                ("trips", a.trips.to_string(), b.trips.to_string()),
+            ];
+            println!("{:<10} {:>17} {:>17}", "", a.id, b.id);
+            for (name, a, b) in rows {
+                println!("{:<10} {:>17} {:>17}", name, a, b);
+            }
+        }
+        HistoryCommand::Progress { athlete, weeks } => {
+            let progress = history.weekly_progress(&athlete, weeks)?;
+            let most_jumps = progress
+                .iter()
+                .map(|week| week.jump_count)
+                .fold(0.0, f32::max);
+
+            println!(
+                "{:<10} {:>8} {:>7} {:>9}",
+                "week of", "sessions", "jumps", "mean bpm"
+            );
+            for week in progress {
+                // the bar is relative to the week with most jumps
+                let bar_len =
+                    (week.jump_count / most_jumps.max(1.0) * 40.0) as usize;
+                println!(
+                    "{:<10} {:>8} {:>7.0} {:>9} {}",
+                    format_date(week.monday),
+                    week.sessions,
+                    week.jump_count,
+                    optional_bpm(week.mean_frequency),
+                    "#".repeat(bar_len),
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// "$XDG_DATA_HOME/jump-rope/sessions.sqlite", or in "~/.local/share" if the
+/// variable isn't set.
+pub fn default_path() -> AppResult<PathBuf> {
+    let data_dir = match env::var_os("XDG_DATA_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let home = env::var_os("HOME").ok_or_else(|| {
+                Error::InvalidArgument("Cannot find home dir".to_string())
+            })?;
+            Path::new(&home).join(".local").join("share")
+        }
+    };
+    let dir = data_dir.join("jump-rope");
+    std::fs::create_dir_all(&dir)?;
+
+    Ok(dir.join("sessions.sqlite"))
+}
+
+impl History {
+    pub fn open(path: impl AsRef<Path>) -> AppResult<Self> {
+        Self::new(Connection::open(path)?)
+    }
+
+    pub fn new(conn: Connection) -> AppResult<Self> {
+        conn.execute_batch(SCHEMA)?;
+
+        Ok(Self { conn })
+    }
+
+    /// Creates the athlete if they don't exist yet. The weight is only
+    /// updated if given.
+    pub fn athlete(
+        &self,
+        name: &str,
+        weight_kg: Option<f32>,
+    ) -> AppResult<i64> {
+        self.conn.execute(
+            "INSERT INTO athletes (name, weight_kg) VALUES (?1, ?2)
+             ON CONFLICT (name)
+             DO UPDATE SET weight_kg = coalesce(?2, weight_kg)",
+            params![name, weight_kg.map(f64::from)],
+        )?;
+
+        Ok(self.conn.query_row(
+            "SELECT id FROM athletes WHERE name = ?1",
+            params![name],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// All sessions, optionally of a single athlete, from the newest.
+    pub fn sessions(
+        &self,
+        athlete: Option<&str>,
+    ) -> AppResult<Vec<SessionSummary>> {
+        let mut statement = self.conn.prepare(&format!(
+            "{} WHERE ?1 IS NULL OR athletes.name = ?1
+             GROUP BY sessions.id
+             ORDER BY sessions.started_at DESC, sessions.id DESC",
+            SUMMARY_QUERY
+        ))?;
+        let sessions = statement
+            .query_map(params![athlete], SessionSummary::from_row)?
+            .collect::<StdResultSql<_>>()?;
+
+        Ok(sessions)
+    }
+
+    pub fn session(&self, id: i64) -> AppResult<Option<SessionSummary>> {
+        Ok(self
+            .conn
+            .query_row(
+                &format!(
+                    "{} WHERE sessions.id = ?1 GROUP BY sessions.id",
+                    SUMMARY_QUERY
+                ),
+                params![id],
+                SessionSummary::from_row,
+            )
+            .optional()?)
+    }
+
+    /// Sessions of the athlete summed by week, for given number of weeks
+    /// until this week, from the oldest. Weeks without sessions are included.
+    pub fn weekly_progress(
+        &self,
+        athlete: &str,
+        weeks: usize,
+    ) -> AppResult<Vec<WeekProgress>> {
+        let this_monday = monday_of(unix_now());
+        let mut progress: Vec<_> = (0..weeks as i64)
+            .rev()
+            .map(|week| WeekProgress {
+                monday: this_monday - week * 7,
+                sessions: 0,
+                jump_count: 0.0,
+                mean_frequency: None,
+            })
+            .collect();
+
+        let mut frequencies = vec![vec![]; progress.len()];
+        for session in self.sessions(Some(athlete))? {
+            let monday = monday_of(session.started_at);
+            let index = match progress.iter().position(|w| w.monday == monday) {
+                Some(index) => index,
+                None => continue,
+            };
+
+            progress[index].sessions += 1;
+            progress[index].jump_count += session.jump_count;
+            frequencies[index].extend(session.mean_frequency);
+        }
+
+        for (week, frequencies) in progress.iter_mut().zip(frequencies) {
+            if !frequencies.is_empty() {
+                week.mean_frequency = Some(
+                    frequencies.iter().sum::<f32>() / frequencies.len() as f32,
+                );
+            }
+        }
+
+        Ok(progress)
+    }
+}
+
+// Sessions with their consensus aggregated. To be followed by a where and a
+// group by clause.
+const SUMMARY_QUERY: &str = "
+    SELECT sessions.id, athletes.name, sessions.source, sessions.started_at,
+        sessions.ended_at, sessions.jump_count, avg(timeline.frequency),
+        max(timeline.frequency), count(timeline.frequency),
+        count(timeline.seconds),
+        (SELECT count(*) FROM activity
+            WHERE session_id = sessions.id AND change = 'started'),
+        (SELECT count(*) FROM activity
+            WHERE session_id = sessions.id AND change = 'recovered')
+    FROM sessions
+    JOIN athletes ON athletes.id = sessions.athlete_id
+    LEFT JOIN timeline ON timeline.session_id = sessions.id
+";
+
+type StdResultSql<T> = std::result::Result<T, rusqlite::Error>;
+
+impl SessionSummary {
+    fn from_row(row: &rusqlite::Row) -> StdResultSql<Self> {
+        let with_consensus: i64 = row.get(8)?;
+        let consensus_count: i64 = row.get(9)?;
+        let sets: i64 = row.get(10)?;
+        let trips: i64 = row.get(11)?;
+
+        Ok(Self {
+            id: row.get(0)?,
+            athlete: row.get(1)?,
+            source: row.get(2)?,
+            started_at: row.get(3)?,
+            ended_at: row.get(4)?,
+            jump_count: row.get::<_, f64>(5)? as f32,
+            mean_frequency: row.get::<_, Option<f64>>(6)?.map(|f| f as f32),
+            peak_frequency: row.get::<_, Option<f64>>(7)?.map(|f| f as f32),
+            active_ratio: with_consensus as f32 / consensus_count.max(1) as f32,
+            sets: sets as usize,
+            trips: trips as usize,
+        })
+    }
+
+    pub fn minutes(&self) -> f32 {
+        (self.ended_at - self.started_at) as f32 / 60.0
+    }
+}
+
+impl SessionRecorder {
+    /// Starts a new session of the athlete now.
+    pub fn start(
+        history: History,
+        athlete_id: i64,
+        source: &str,
+    ) -> AppResult<Self> {
+        let now = unix_now();
+        history.conn.execute(
+            "INSERT INTO sessions (athlete_id, source, started_at, ended_at)
+             VALUES (?1, ?2, ?3, ?3)",
+            params![athlete_id, source, now],
+        )?;
+        let session_id = history.conn.last_insert_rowid();
+
+        Ok(Self {
+            history,
+            session_id,
+            latest: None,
+            jump_count: 0.0,
+        })
+    }
+}
+
+impl TraceSink for SessionRecorder {
+    fn write(&mut self, event: &TraceEvent) -> AppResult<()> {
+        let (seconds, frequency) = match event {
+            TraceEvent::Consensus { seconds, frequency } => {
+                (*seconds, *frequency)
+            }
+            TraceEvent::Activity { seconds, change } => {
+                self.history.conn.execute(
+                    "INSERT INTO activity (session_id, seconds, change)
+                     VALUES (?1, ?2, ?3)",
+                    params![self.session_id, seconds, change.as_str()],
+                )?;
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
+
+        // the athlete has been jumping at the previous consensus since then
+        if let Some((since, Some(hz))) = self.latest {
+            self.jump_count += hz * (seconds - since).max(0.0) as f32;
+        }
+        self.latest = Some((seconds, frequency));
+
+        let tx = self.history.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO timeline (session_id, seconds, frequency)
+             VALUES (?1, ?2, ?3)",
+            params![self.session_id, seconds, frequency.map(f64::from)],
+        )?;
+        tx.execute(
+            "UPDATE sessions SET ended_at = ?2, jump_count = ?3 WHERE id = ?1",
+            params![self.session_id, unix_now(), self.jump_count as f64],
+        )?;
+        tx.commit()?;
+
+        Ok(())
+    }
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Clock is before 1970")
+        .as_secs() as i64
+}
+
+// Days since the epoch of the Monday of the week of given unix timestamp.
+fn monday_of(timestamp: i64) -> i64 {
+    let days = timestamp.div_euclid(86_400);
+    // the epoch was a Thursday
+    days - (days + 3).rem_euclid(7)
+}
+
+fn optional_bpm(frequency: Option<f32>) -> String {
+    frequency
+        .map(|hz| format!("{:.0}", hz * 60.0))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+// E.g. "2021-06-07 18:30" in UTC.
+fn format_timestamp(timestamp: i64) -> String {
+    let minutes = timestamp.rem_euclid(86_400) / 60;
+    format!(
+        "{} {:02}:{:02}",
+        format_date(timestamp.div_euclid(86_400)),
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+// Converts days since the epoch to a date, see
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn format_date(days: i64) -> String {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jump_rope::activity::ActivityChange;
+    use std::time::Duration;
+
+    #[test]
+    fn it_records_and_summarizes_sessions() {
+        let history =
+            History::new(Connection::open_in_memory().unwrap()).unwrap();
+        let athlete = history.athlete("ada", Some(60.0)).unwrap();
+        assert_eq!(history.athlete("ada", None).unwrap(), athlete);
+
+        let mut recorder =
+            SessionRecorder::start(history, athlete, "synthetic:2.5").unwrap();
+        let activity =
+            |seconds, change| TraceEvent::Activity { seconds, change };
+        let events = [
+            TraceEvent::Start { frame_rate: 30 },
+            TraceEvent::consensus(Duration::from_secs(4), None),
+            activity(5.0, ActivityChange::Started),
+            TraceEvent::consensus(Duration::from_secs(5), Some(2.0)),
+            TraceEvent::consensus(Duration::from_secs(6), Some(2.0)),
+            activity(6.5, ActivityChange::Tripped),
+            activity(6.75, ActivityChange::Recovered),
+            TraceEvent::consensus(Duration::from_secs(7), Some(3.0)),
+            TraceEvent::consensus(Duration::from_secs(8), Some(3.0)),
+        ];
+        for event in &events {
+            recorder.write(event).unwrap();
+        }
+        let history = recorder.history;
+
+        let sessions = history.sessions(Some("ada")).unwrap();
+        assert_eq!(sessions.len(), 1);
+        let session = &sessions[0];
+        assert_eq!(session.athlete, "ada");
+        // 2 s at 2 Hz and 1 s at 3 Hz
+        assert_eq!(session.jump_count, 7.0);
+        assert_eq!(session.mean_frequency, Some(2.5));
+        assert_eq!(session.peak_frequency, Some(3.0));
+        assert_eq!(session.active_ratio, 0.8);
+        assert_eq!((session.sets, session.trips), (1, 1));
+        assert_eq!(
+            history.session(session.id).unwrap().as_ref(),
+            Some(session)
+        );
+        assert!(history.sessions(Some("bob")).unwrap().is_empty());
+
+        let progress = history.weekly_progress("ada", 4).unwrap();
+        assert_eq!(progress.len(), 4);
+        assert_eq!(progress[3].sessions, 1);
+        assert_eq!(progress[3].jump_count, 7.0);
+        assert_eq!(progress[2].monday, progress[3].monday - 7);
+    }
+
+    #[test]
+    fn it_formats_dates() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00");
+        assert_eq!(format_timestamp(1_623_090_600), "2021-06-07 18:30");
+        // 2021-06-10 is a Thursday
+        assert_eq!(format_date(monday_of(1_623_283_200)), "2021-06-07");
+    }
+}
@@ -0,0 +1,72 @@
+//! Estimates jump rope cadence from video.
+//!
+//! Frames are read from a [`FrameSource`], e.g. a camera, a video file or a
+//! generated scene, and pushed through a [`Pipeline`]. Its analyzers track
+//! the shade of many small patches of the frames over windows of several
+//! sizes, and each [`Report`]s the frequency most patches agree on. The
+//! [`FrequencyTracker`] reaches a consensus over the reports of all window
+//! sizes, counts the jumps and tells whether the athlete is jumping.
+//!
+//! ```
+//! use jump_rope::source::CadenceScene;
+//! use jump_rope::{Pipeline, PipelineConfig};
+//! use std::time::Duration;
+//!
+//! // a scene of a rope turning at 2.5 Hz
+//! let mut frames = CadenceScene::constant(2.5)
+//!     .with_noise(10)
+//!     .into_source(120, 90, 30)
+//!     .with_duration(Duration::from_secs(20));
+//!
+//! let config = PipelineConfig::default();
+//! let mut pipeline = Pipeline::from_source(&frames, &config)?;
+//! let reports = pipeline.subscribe();
+//! pipeline.run(&mut frames)?;
+//!
+//! assert!(reports.try_iter().count() > 0);
+//! let hz = pipeline.tracker().calculate_latest().unwrap();
+//! assert!((hz - 2.5).abs() < 0.15);
+//! # Ok::<(), jump_rope::Error>(())
+//! ```
+//!
+//! Each analyzer runs on its own thread, and [`Pipeline::run`] waits for all
+//! of them to process a frame before the next one. To follow a live source,
+//! read it on its own thread, push its frames with [`Pipeline::send_frame`]
+//! and share the [`FrequencyTracker`], see [`Pipeline::tracker`]. The source
+//! must be opened on that thread, as [`frame::FrameIter`] cannot be sent
+//! between threads. The pipelines of several sources can share one tracker,
+//! see [`Pipeline::with_tracker`].
+//!
+//! Besides the reports, a pipeline shows what its analyzers see, see
+//! [`preview`] and [`spectrogram`], and how well it keeps up, see
+//! [`metrics`].
+//!
+//! Video files and devices are read with ffmpeg, which must be initialised
+//! first with `ffmpeg_next::init`.
+
+extern crate ffmpeg_next as ffmpeg;
+
+pub mod activity;
+mod analyzer;
+pub mod error;
+pub mod frame;
+pub mod frequency_tracker;
+pub mod metrics;
+mod oscillator;
+pub mod pipeline;
+pub mod preview;
+pub mod rhythm;
+pub mod source;
+pub mod spectrogram;
+
+mod prelude;
+
+pub use crate::analyzer::Report;
+pub use crate::error::Error;
+pub use crate::frequency_tracker::{FrequencyTracker, Snapshot, SourceStatus};
+pub use crate::pipeline::{Pipeline, PipelineConfig};
+pub use crate::prelude::{
+    Result, HIGHEST_FREQUENCY_OF_INTEREST, LOWEST_FREQUENCY_OF_INTEREST,
+    MIN_FRAME_RATE, REPORT_FREQUENCY_AFTER_MS,
+};
+pub use crate::source::{Frame, FrameSource};
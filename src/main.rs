@@ -1,86 +1,483 @@
 extern crate ffmpeg_next as ffmpeg;
 
-mod analyzer;
-mod frame;
-mod frequency_tracker;
-mod oscillator;
-mod prelude;
+mod annotate;
+mod app_error;
+mod cli;
+mod devices;
+mod evaluation;
+mod export;
+mod glyphs;
+mod graph;
+mod history;
+mod metronome;
+mod raster;
+mod server;
+mod tempo;
+mod trace;
 mod ui;
+mod workout;
 
-use crate::analyzer::AnalyzerBuilder;
-use crate::frame::FrameIter;
-use frequency_tracker::FrequencyTracker;
+use crate::app_error::AppResult;
+use crate::cli::{Args, Command, SourceArgs};
+use crate::export::TimelineWriter;
+use crate::history::{History, SessionRecorder};
+use crate::trace::{TraceEvent, TraceSink, TraceWriter};
+use crate::workout::Workout;
+use jump_rope::frequency_tracker::{FrequencyTracker, SourceStatus};
+use jump_rope::metrics::PipelineMetrics;
+use jump_rope::pipeline::{Pipeline, PipelineConfig};
+use jump_rope::preview::Preview;
+use jump_rope::source::FrameSource;
+use jump_rope::spectrogram::Spectrogram;
+use jump_rope::{Error, Result};
+use rand::{thread_rng, Rng};
+use std::collections::VecDeque;
+use std::process;
 use std::sync::mpsc::channel;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
+use structopt::StructOpt;
 
 fn main() {
-    ffmpeg::init().unwrap();
+    // setup failures are reported rather than panicking, errors of the
+    // analysis later on are shown as the status of the source
+    if let Err(e) = run(Args::from_args()) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+// Runs the command, or the live analysis with its UI until the window is
+// closed.
+fn run(args: Args) -> AppResult<()> {
+    ffmpeg::init().map_err(Error::from)?;
+
+    // shared by the pipelines of all sources
+    let metrics = Arc::new(PipelineMetrics::new());
+    if let Some(addr) = &args.metrics {
+        let addr = server::serve_metrics(addr, Arc::clone(&metrics))?;
+        println!("Serving metrics on http://{}", addr);
+    }
+
+    let sources = args.sources();
+    let (frequency_tracker, preview, spectrogram) = match args.command {
+        Some(Command::Annotate(args)) => {
+            annotate::run(args)?;
+            return Ok(());
+        }
+        Some(Command::Devices) => {
+            devices::run()?;
+            return Ok(());
+        }
+        Some(Command::Evaluate(args)) => {
+            evaluation::run(args)?;
+            return Ok(());
+        }
+        Some(Command::Export(args)) => {
+            export::from_trace(&args.trace, &args.out)?;
+            return Ok(());
+        }
+        Some(Command::History(command)) => {
+            history::run(args.history.as_deref(), command)?;
+            return Ok(());
+        }
+        Some(Command::Metronome(args)) => {
+            metronome::render_trace(
+                &args.trace,
+                &args.out,
+                args.bpm,
+                args.tolerance,
+            )?;
+            return Ok(());
+        }
+        Some(Command::Replay(args)) => {
+            let events = trace::read(&args.trace)?;
+            let tracker = trace::replay(events, args.speed)?;
+            (tracker, None, None)
+        }
+        None => {
+            let mut sinks: Vec<Box<dyn TraceSink>> = vec![];
+            if let Some(path) = args.record {
+                let writer = TraceWriter::create(path)?;
+                sinks.push(Box::new(writer));
+            }
+            if let Some(path) = args.export {
+                let writer = TimelineWriter::create(path)?;
+                sinks.push(Box::new(writer));
+            }
+            if let Some(athlete) = &args.athlete {
+                let path = match &args.history {
+                    Some(path) => path.clone(),
+                    None => history::default_path()?,
+                };
+                let history = History::open(path)?;
+                let athlete_id = history.athlete(athlete, args.weight_kg)?;
+                let recorder = SessionRecorder::start(
+                    history,
+                    athlete_id,
+                    &args.source.source,
+                )?;
+                sinks.push(Box::new(recorder));
+            }
+
+            let (tracker, preview, spectrogram) =
+                start_video_analysis(sources, sinks, metrics)?;
+            (tracker, Some(preview), Some(spectrogram))
+        }
+    };
+
+    if let Some(addr) = args.serve {
+        let addr = server::start(addr, Arc::clone(&frequency_tracker))?;
+        println!("Serving cadence on http://{}", addr);
+    }
+    if let Some(addr) = args.osc {
+        tempo::start_osc(addr, Arc::clone(&frequency_tracker))?;
+    }
+    if let Some(port_name) = args.midi_clock {
+        tempo::start_midi_clock(&port_name, Arc::clone(&frequency_tracker))?;
+    }
+    if let Some(bpm) = args.metronome {
+        let tracker = Arc::clone(&frequency_tracker);
+        metronome::play(bpm, args.tolerance, tracker)?;
+    }
 
-    let frequency_tracker = start_video_analysis();
+    let workout = match args.workout {
+        Some(path) => Some(Workout::from_file(path)?),
+        None => None,
+    };
 
     // bevy must always run on main thread
-    ui::start(frequency_tracker);
+    ui::start(
+        frequency_tracker,
+        workout,
+        preview,
+        spectrogram,
+        args.show_windows,
+    );
+
+    Ok(())
+}
+
+// How many seconds of frames to remember for measuring the consensus latency.
+// The analyzers must not lag behind more than this for the latency to show.
+const LATENCY_HISTORY_SECONDS: usize = 10;
+
+// How long to wait between attempts to open a lost device again.
+const RECONNECT_AFTER_MS: u64 = 1000;
+
+// What the pipelines of a source report into besides the tracker, kept
+// across its connections.
+struct Outputs {
+    preview: Option<Arc<Preview>>,
+    spectrogram: Option<Arc<Spectrogram>>,
+    metrics: Arc<PipelineMetrics>,
 }
 
-// Starts iterating the video frames with various window sizes and updates the
-// tracker with latest values.
+// Starts iterating the video frames of each source with various window sizes
+// and updates the tracker with latest values, see
+// [`FrequencyTracker::update_from`].
+//
+// All reports and consensus values are written to the sinks as they happen.
 //
-// Returns a shared state abstraction to read the latest frequency.
-fn start_video_analysis() -> Arc<FrequencyTracker> {
-    // creates new one shot channel to send shared state reference because:
-    // 1. bevy must run on the main thread
-    // 2. [`FrameIter`] cannot be shared between threads safely after
-    //    initialization
-    let (sender, receiver) = channel();
-    thread::spawn(move || {
-        // TODO: camera might not be on this device
-        let file = "/dev/video0";
-        let frames = FrameIter::from_file(file).expect("Cannot load video");
-        let frame_rate = frames.frame_rate();
-        println!("FPS: {}", frame_rate);
-
-        // create shared state abstraction and send a clone reference to
-        // the main thread
-        let frequency_tracker = Arc::new(FrequencyTracker::new(frame_rate));
-        sender.send(Arc::clone(&frequency_tracker)).unwrap();
-
-        // The larger the multiplier, the more granular frequency intervals it
-        // can find. However, it takes longer to start reporting and it takes
-        // longer to adjust to rapid speed changes.
-        //
-        // We therefore spawn multiple and let them reach a consensus.
-        const WINDOW_MULTIPLIERS: &[usize] = &[4, 8, 12];
-        let channels: Vec<_> = WINDOW_MULTIPLIERS
-            .iter()
-            .map(|multiplier| {
-                analyzer::channel(AnalyzerBuilder {
-                    frame_rate,
-                    window: frame_rate * *multiplier,
-                    frame_height: frames.height(),
-                    frame_width: frames.width(),
-                })
-            })
-            .collect();
-
-        for frame in frames {
-            // update each analyzer (they differ by window) with the new frame
-            let frame = Arc::new(frame);
-            channels.iter().for_each(|(frame_sender, _)| {
-                frame_sender.send(Arc::clone(&frame)).expect("Channel dead")
-            });
-
-            // TODO: if no update for long time, clean the tracker
-            //
-            // check for frequency updates
-            for (_, frequency_recv) in &channels {
-                // we only care about the freshest value
-                if let Some(report) = frequency_recv.try_iter().last() {
-                    frequency_tracker.update(report);
+// The metrics of all sources are kept together, labelled by the source.
+//
+// Returns a shared state abstraction to read the latest frequency, the
+// preview of what the analysis sees and the spectrogram of all analyzers of
+// the first source, or the error if a source cannot be opened. Errors which
+// happen later are published as the status of the source, see
+// [`SourceStatus`].
+fn start_video_analysis(
+    sources: Vec<SourceArgs>,
+    sinks: Vec<Box<dyn TraceSink>>,
+    metrics: Arc<PipelineMetrics>,
+) -> AppResult<(Arc<FrequencyTracker>, Arc<Preview>, Arc<Spectrogram>)> {
+    let preview = Arc::new(Preview::new());
+    let spectrogram = Arc::new(Spectrogram::new());
+    // written to by the threads of all sources
+    let sinks = Arc::new(Mutex::new(sinks));
+
+    // each source is read on its own thread, because [`FrameIter`] cannot be
+    // shared between threads safely after initialization, and bevy must run
+    // on the main thread
+    //
+    // the threads send the frame rate of their source once it's open, and
+    // wait for the shared state abstraction, which needs the frame rate
+    let sources_count = sources.len();
+    let (started_sender, started_receiver) = channel();
+    let mut tracker_senders = vec![];
+    for (index, source) in sources.into_iter().enumerate() {
+        let started_sender = started_sender.clone();
+        let (tracker_sender, tracker_receiver) = channel();
+        tracker_senders.push(tracker_sender);
+        let sinks = Arc::clone(&sinks);
+        // only the first source is previewed
+        let (preview, spectrogram) = if index == 0 {
+            (Some(Arc::clone(&preview)), Some(Arc::clone(&spectrogram)))
+        } else {
+            (None, None)
+        };
+        let outputs = Outputs {
+            preview,
+            spectrogram,
+            metrics: Arc::clone(&metrics),
+        };
+
+        thread::spawn(move || {
+            // nobody waits for the source anymore if the setup failed
+            // meanwhile
+            let frames = match source.open() {
+                Ok(frames) => frames,
+                Err(e) => {
+                    let _ = started_sender.send((index, Err(e)));
+                    return;
                 }
+            };
+            if started_sender
+                .send((index, Ok(frames.frame_rate())))
+                .is_err()
+            {
+                return;
+            }
+
+            // called off when another source cannot be opened
+            let frequency_tracker: Arc<FrequencyTracker> =
+                match tracker_receiver.recv() {
+                    Ok(frequency_tracker) => frequency_tracker,
+                    Err(_) => return,
+                };
+
+            let analysed = analyse_source(
+                index,
+                &source,
+                frames,
+                &frequency_tracker,
+                &sinks,
+                &outputs,
+            );
+            if let Err(e) = analysed {
+                eprintln!("Analysis of {} stopped: {}", source.source, e);
+                frequency_tracker
+                    .set_status_of(index, SourceStatus::Failed(e.to_string()));
+            }
+        });
+    }
+    drop(started_sender);
+
+    let mut started: Vec<_> =
+        started_receiver.iter().take(sources_count).collect();
+    if started.len() < sources_count {
+        return Err(Error::ThreadStopped.into());
+    }
+    started.sort_by_key(|(index, _)| *index);
+    let frame_rates = started
+        .into_iter()
+        .map(|(_, frame_rate)| frame_rate)
+        .collect::<Result<Vec<_>>>()?;
+
+    // the reports of all sources are fused on a shared timeline
+    let frame_rate = frame_rates[0];
+    if let Some(other) = frame_rates.iter().find(|fps| **fps != frame_rate) {
+        return Err(Error::FrameRateMismatch {
+            expected: frame_rate,
+            actual: *other,
+        }
+        .into());
+    }
+    println!("FPS: {}", frame_rate);
+
+    let frequency_tracker = Arc::new(FrequencyTracker::new(frame_rate));
+    write_to_sinks(&sinks, TraceEvent::Start { frame_rate })?;
+    for tracker_sender in tracker_senders {
+        tracker_sender
+            .send(Arc::clone(&frequency_tracker))
+            .map_err(Error::from)?;
+    }
+
+    Ok((frequency_tracker, preview, spectrogram))
+}
+
+// Analyses the source of given index until it ends. A lost device is opened
+// again, and the analysis carries on with new analyzers. The timeline
+// continues where it left off, including the time it took to reconnect.
+fn analyse_source(
+    source_index: usize,
+    source: &SourceArgs,
+    mut frames: Box<dyn FrameSource>,
+    frequency_tracker: &Arc<FrequencyTracker>,
+    sinks: &Mutex<Vec<Box<dyn TraceSink>>>,
+    outputs: &Outputs,
+) -> AppResult<()> {
+    let frame_rate = frames.frame_rate();
+
+    // the frames of each connection start at zero
+    let mut offset = Duration::default();
+    loop {
+        let last_timestamp = analyse_frames(
+            source_index,
+            frames.as_mut(),
+            offset,
+            frequency_tracker,
+            sinks,
+            outputs,
+        )?;
+
+        let error = frames.take_error();
+        if !source.is_device() {
+            if let Some(e) = error {
+                return Err(e.into());
             }
+            frequency_tracker.set_status_of(source_index, SourceStatus::Ended);
+            return Ok(());
         }
-    });
 
-    receiver.recv().unwrap()
+        // devices don't end, even without an error they are gone
+        let error = error.unwrap_or_else(|| {
+            Error::Disconnected("No more frames".to_string())
+        });
+        eprintln!("{}: {}, reconnecting", source.source, error);
+        frequency_tracker.set_status_of(
+            source_index,
+            SourceStatus::Reconnecting(error.to_string()),
+        );
+
+        let lost_at = Instant::now();
+        frames = loop {
+            thread::sleep(Duration::from_millis(RECONNECT_AFTER_MS));
+            match source.open() {
+                Ok(frames) => break frames,
+                Err(e) => frequency_tracker.set_status_of(
+                    source_index,
+                    SourceStatus::Reconnecting(e.to_string()),
+                ),
+            }
+        };
+        if frames.frame_rate() != frame_rate {
+            return Err(Error::FrameRateMismatch {
+                expected: frame_rate,
+                actual: frames.frame_rate(),
+            }
+            .into());
+        }
+        println!(
+            "Reconnected {} after {:.1}s",
+            source.source,
+            lost_at.elapsed().as_secs_f32()
+        );
+
+        // the reports of the previous analyzers would outvote the new ones
+        frequency_tracker.reset_reports(source_index);
+        frequency_tracker.set_status_of(source_index, SourceStatus::Running);
+        offset = last_timestamp + lost_at.elapsed();
+    }
+}
+
+// Analyses the frames of a single connection to the source with a new
+// pipeline, the timestamps of the frames shifted by the offset. Returns the
+// timestamp of the last frame.
+fn analyse_frames(
+    source_index: usize,
+    frames: &mut dyn FrameSource,
+    offset: Duration,
+    frequency_tracker: &Arc<FrequencyTracker>,
+    sinks: &Mutex<Vec<Box<dyn TraceSink>>>,
+    outputs: &Outputs,
+) -> AppResult<Duration> {
+    let frame_rate = frames.frame_rate();
+
+    // the oscillators are placed anew with each connection
+    let config = PipelineConfig {
+        seed: thread_rng().gen(),
+        ..PipelineConfig::default()
+    };
+    let mut pipeline = Pipeline::from_source(&*frames, &config)?
+        .with_tracker(Arc::clone(frequency_tracker), source_index)
+        .with_metrics(Arc::clone(&outputs.metrics));
+    if let Some(preview) = &outputs.preview {
+        pipeline = pipeline.with_preview(Arc::clone(preview));
+    }
+    if let Some(spectrogram) = &outputs.spectrogram {
+        pipeline = pipeline.with_spectrogram(Arc::clone(spectrogram));
+    }
+
+    // when were the recent frames read, to measure how long it takes
+    // until their reports get into the consensus
+    let mut read_at = VecDeque::new();
+    let mut last_timestamp = offset;
+    // the consensus of the latest consensus event
+    let mut recorded_consensus = None;
+
+    for mut frame in frames {
+        frame.timestamp += offset;
+        last_timestamp = frame.timestamp;
+
+        read_at.push_back((frame.timestamp, Instant::now()));
+        if read_at.len() > frame_rate * LATENCY_HISTORY_SECONDS {
+            read_at.pop_front();
+        }
+
+        // the analyzers don't hold up reading the frames, their reports
+        // are of whichever frames they processed meanwhile
+        let frame = Arc::new(frame);
+        if let Some(preview) = &outputs.preview {
+            preview.set_frame(Arc::clone(&frame));
+        }
+        let reports = pipeline.send_frame(Arc::clone(&frame))?;
+
+        let mut newest_report: Option<Duration> = None;
+        for report in &reports {
+            write_to_sinks(sinks, TraceEvent::report(source_index, report))?;
+            newest_report = newest_report.max(Some(report.timestamp));
+        }
+
+        if let Some(report_timestamp) = newest_report {
+            let consensus = frequency_tracker.calculate_latest();
+            if let Some((_, frame_read_at)) =
+                read_at.iter().find(|(t, _)| *t == report_timestamp)
+            {
+                outputs
+                    .metrics
+                    .consensus_latency_seconds
+                    .set(frame_read_at.elapsed().as_secs_f64());
+            }
+            write_to_sinks(
+                sinks,
+                TraceEvent::consensus(frame.timestamp, consensus),
+            )?;
+            recorded_consensus = consensus;
+        }
+
+        // with each frame, as stops show by reports not arriving
+        if let Some(event) = frequency_tracker.tick(frame.timestamp) {
+            println!(
+                "Activity {} at {:.2}s",
+                event.change.as_str(),
+                event.at.as_secs_f32()
+            );
+            write_to_sinks(sinks, TraceEvent::from(&event))?;
+        }
+
+        // the jumps are counted up to here in the history and the export
+        let lost = frequency_tracker.calculate_latest().is_none();
+        if lost && recorded_consensus.is_some() {
+            write_to_sinks(
+                sinks,
+                TraceEvent::consensus(frame.timestamp, None),
+            )?;
+            recorded_consensus = None;
+        }
+    }
+
+    Ok(last_timestamp)
+}
+
+fn write_to_sinks(
+    sinks: &Mutex<Vec<Box<dyn TraceSink>>>,
+    event: TraceEvent,
+) -> AppResult<()> {
+    for sink in sinks.lock().unwrap().iter_mut() {
+        sink.write(&event)?;
+    }
+
+    Ok(())
 }
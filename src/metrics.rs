@@ -0,0 +1,338 @@
+//! Health of a [`Pipeline`] in the Prometheus text format, see
+//! [`Pipeline::metrics`].
+//!
+//! Each pipeline has its own metrics, unless they are shared between the
+//! pipelines of several sources, see [`Pipeline::with_metrics`]. The metrics
+//! of the analyzers are labelled by their source then.
+//!
+//! [`Pipeline`]: crate::pipeline::Pipeline
+//! [`Pipeline::metrics`]: crate::pipeline::Pipeline::metrics
+//! [`Pipeline::with_metrics`]: crate::pipeline::Pipeline::with_metrics
+
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Metrics of one or more pipelines and their analyzers.
+#[derive(Default)]
+pub struct PipelineMetrics {
+    /// Incremented for each frame pushed into the pipelines, as they are
+    /// decoded.
+    pub frames_decoded: Counter,
+    /// How many frames were decoded per second, over the last second or
+    /// longer, see [`PipelineMetrics::render`].
+    pub frames_decoded_per_second: Gauge,
+    // When did the second we count frames for in
+    // [`PipelineMetrics::frames_decoded_per_second`] start, and the total
+    // count of frames at that point.
+    current_second: Mutex<Option<(Instant, u64)>>,
+    // How many frames were sent to the analyzers of each source, indexed by
+    // the source.
+    frames_dispatched: Mutex<Vec<u64>>,
+    /// Seconds from a frame being read to the consensus including its reports
+    /// being calculated. Only whoever reads the frames can tell, therefore
+    /// the pipeline leaves it to them.
+    pub consensus_latency_seconds: Gauge,
+    // Metrics of each running analyzer.
+    analyzers: Mutex<Vec<Arc<AnalyzerMetrics>>>,
+}
+
+// Metrics of a single analyzer of a [`Pipeline`], labelled by its source and
+// its window size.
+//
+// [`Pipeline`]: crate::pipeline::Pipeline
+pub(crate) struct AnalyzerMetrics {
+    source: usize,
+    window: usize,
+    // Frames of the source dispatched before the analyzer was registered,
+    // which it never gets.
+    dispatched_before: u64,
+    /// Each frame sent to the analyzer is eventually processed. The queue
+    /// depth is the difference to the frames of its source dispatched since
+    /// the analyzer was registered, see [`PipelineMetrics::frame_dispatched`].
+    pub frames_processed: Counter,
+    /// How long calculating the frequency took for the latest report.
+    pub fft_latest_seconds: Gauge,
+    /// Summary of how long calculating the frequency took for all reports.
+    pub fft_seconds_sum: Gauge,
+    pub fft_seconds_count: Counter,
+    /// Oscillators whose values vary enough to be considered for the latest
+    /// report.
+    pub oscillators_oscillating: Gauge,
+}
+
+/// Monotonically increasing value.
+#[derive(Default)]
+pub struct Counter(AtomicU64);
+
+/// Value which can go up and down.
+#[derive(Default)]
+pub struct Gauge(AtomicU64);
+
+impl PipelineMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn frame_decoded(&self) {
+        self.frames_decoded.inc();
+        self.roll_second(Instant::now());
+    }
+
+    // Updates the frame rate once a second passed since the last update.
+    // Called with each frame, and with each render, so that the rate drops
+    // to 0 when the source stalls or disconnects.
+    fn roll_second(&self, now: Instant) {
+        let total = self.frames_decoded.get();
+        let mut current_second = self.current_second.lock().unwrap();
+        let (started_at, total_at_start) =
+            *current_second.get_or_insert((now, total));
+        let elapsed = now.saturating_duration_since(started_at);
+        if elapsed >= Duration::from_secs(1) {
+            let rate = (total - total_at_start) as f64 / elapsed.as_secs_f64();
+            self.frames_decoded_per_second.set(rate);
+            *current_second = Some((now, total));
+        }
+    }
+
+    /// To be called for each frame of given source sent to all of its
+    /// analyzers.
+    pub(crate) fn frame_dispatched(&self, source: usize) {
+        let mut dispatched = self.frames_dispatched.lock().unwrap();
+        if dispatched.len() <= source {
+            dispatched.resize(source + 1, 0);
+        }
+        dispatched[source] += 1;
+    }
+
+    // How many frames of given source were sent to its analyzers.
+    fn dispatched(&self, source: usize) -> u64 {
+        let dispatched = self.frames_dispatched.lock().unwrap();
+        dispatched.get(source).copied().unwrap_or(0)
+    }
+
+    /// Creates metrics for a new analyzer. They are rendered from now on,
+    /// instead of those of the previous analyzer of the same source and
+    /// window size.
+    pub(crate) fn register_analyzer(
+        &self,
+        source: usize,
+        window: usize,
+    ) -> Arc<AnalyzerMetrics> {
+        let metrics = Arc::new(AnalyzerMetrics {
+            source,
+            window,
+            dispatched_before: self.dispatched(source),
+            frames_processed: Counter::default(),
+            fft_latest_seconds: Gauge::default(),
+            fft_seconds_sum: Gauge::default(),
+            fft_seconds_count: Counter::default(),
+            oscillators_oscillating: Gauge::default(),
+        });
+        let mut analyzers = self.analyzers.lock().unwrap();
+        // e.g. the analyzers are replaced when the source reconnects
+        analyzers.retain(|analyzer| {
+            (analyzer.source, analyzer.window) != (source, window)
+        });
+        analyzers.push(Arc::clone(&metrics));
+
+        metrics
+    }
+
+    /// Formats all metrics in the Prometheus text exposition format. The
+    /// frame rate is updated first, in case no frames arrive anymore.
+    pub fn render(&self) -> String {
+        self.roll_second(Instant::now());
+        let mut out = String::new();
+
+        let mut single = |name: &str, kind: &str, help: &str, value: f64| {
+            header(&mut out, name, kind, help);
+            writeln!(out, "jump_rope_{} {}", name, value).unwrap();
+        };
+        single(
+            "frames_decoded_total",
+            "counter",
+            "Frames decoded.",
+            self.frames_decoded.get() as f64,
+        );
+        single(
+            "frames_decoded_per_second",
+            "gauge",
+            "Frames decoded per second, over at least the last second.",
+            self.frames_decoded_per_second.get(),
+        );
+        single(
+            "consensus_latency_seconds",
+            "gauge",
+            "Time from reading a frame to the consensus including it.",
+            self.consensus_latency_seconds.get(),
+        );
+
+        let analyzers = self.analyzers.lock().unwrap();
+        let labels = |analyzer: &AnalyzerMetrics| {
+            format!(
+                "{{source=\"{}\",window=\"{}\"}}",
+                analyzer.source, analyzer.window
+            )
+        };
+        let mut per_analyzer =
+            |name: &str,
+             kind: &str,
+             help: &str,
+             value: &dyn Fn(&AnalyzerMetrics) -> f64| {
+                header(&mut out, name, kind, help);
+                for analyzer in analyzers.iter() {
+                    writeln!(
+                        out,
+                        "jump_rope_{}{} {}",
+                        name,
+                        labels(analyzer),
+                        value(analyzer)
+                    )
+                    .unwrap();
+                }
+            };
+
+        per_analyzer(
+            "analyzer_queue_depth",
+            "gauge",
+            "Frames waiting for the analyzer.",
+            &|a| {
+                let dispatched = self
+                    .dispatched(a.source)
+                    .saturating_sub(a.dispatched_before);
+                dispatched.saturating_sub(a.frames_processed.get()) as f64
+            },
+        );
+        per_analyzer(
+            "analyzer_fft_latest_seconds",
+            "gauge",
+            "Time to calculate the frequency of the latest report.",
+            &|a| a.fft_latest_seconds.get(),
+        );
+        per_analyzer(
+            "analyzer_oscillators_oscillating",
+            "gauge",
+            "Oscillators passing the variance filter in the latest report.",
+            &|a| a.oscillators_oscillating.get(),
+        );
+
+        // a summary without quantiles is just the sum and the count
+        header(
+            &mut out,
+            "analyzer_fft_seconds",
+            "summary",
+            "Time to calculate the frequency of each report.",
+        );
+        for analyzer in analyzers.iter() {
+            let labels = labels(analyzer);
+            writeln!(
+                out,
+                "jump_rope_analyzer_fft_seconds_sum{} {}",
+                labels,
+                analyzer.fft_seconds_sum.get()
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "jump_rope_analyzer_fft_seconds_count{} {}",
+                labels,
+                analyzer.fft_seconds_count.get()
+            )
+            .unwrap();
+        }
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP jump_rope_{} {}", name, help).unwrap();
+    writeln!(out, "# TYPE jump_rope_{} {}", name, kind).unwrap();
+}
+
+impl Counter {
+    pub(crate) fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Gauge {
+    pub fn set(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Only one thread updates each gauge, so there's no race between the
+    /// load and the store.
+    pub(crate) fn add(&self, value: f64) {
+        self.set(self.get() + value);
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_renders_metrics() {
+        let metrics = PipelineMetrics::new();
+        // e.g. the source reconnected and its analyzers were replaced
+        metrics.register_analyzer(0, 120);
+        for _ in 0..3 {
+            metrics.frame_dispatched(0);
+        }
+        let analyzer = metrics.register_analyzer(0, 120);
+        // the frames of another source don't queue for this analyzer
+        let other = metrics.register_analyzer(1, 120);
+
+        for _ in 0..5 {
+            metrics.frame_decoded();
+            metrics.frame_dispatched(0);
+        }
+        metrics.frame_dispatched(1);
+        other.frames_processed.inc();
+        analyzer.frames_processed.inc();
+        analyzer.fft_latest_seconds.set(0.25);
+        analyzer.fft_seconds_sum.add(0.25);
+        analyzer.fft_seconds_count.inc();
+
+        let rendered = metrics.render();
+        for line in &[
+            "# TYPE jump_rope_frames_decoded_total counter",
+            "jump_rope_frames_decoded_total 5",
+            "jump_rope_analyzer_queue_depth{source=\"0\",window=\"120\"} 4",
+            "jump_rope_analyzer_queue_depth{source=\"1\",window=\"120\"} 0",
+            "# TYPE jump_rope_analyzer_fft_seconds summary",
+            "jump_rope_analyzer_fft_seconds_sum{source=\"0\",window=\"120\"} 0.25",
+            "jump_rope_analyzer_fft_seconds_count{source=\"0\",window=\"120\"} 1",
+        ] {
+            assert!(rendered.lines().any(|l| l == *line), "{}", rendered);
+        }
+    }
+
+    #[test]
+    fn it_drops_frame_rate_when_frames_stop() {
+        let metrics = PipelineMetrics::new();
+        let start = Instant::now();
+        metrics.roll_second(start);
+        for i in 0..30 {
+            metrics.frames_decoded.inc();
+            metrics.roll_second(start + Duration::from_millis(i * 33));
+        }
+        metrics.roll_second(start + Duration::from_secs(1));
+        assert_eq!(metrics.frames_decoded_per_second.get(), 30.0);
+
+        // e.g. rendering for a scrape while the source is stalled
+        metrics.roll_second(start + Duration::from_secs(2));
+        assert_eq!(metrics.frames_decoded_per_second.get(), 0.0);
+    }
+}
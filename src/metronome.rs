@@ -0,0 +1,322 @@
+//! Audio feedback on pace, so that the athlete doesn't need to watch the
+//! graph.
+//!
+//! A click is played at the target cadence. While the cadence is outside the
+//! tolerance band around the target, each click is replaced by a longer cue:
+//! a high tone to speed up, or a low tone to slow down.
+
+use crate::app_error::{AppError, AppResult};
+use crate::trace::{self, TraceEvent};
+use jump_rope::frequency_tracker::FrequencyTracker;
+use jump_rope::{Error, Result};
+use rodio::{OutputStream, Source};
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const SAMPLE_RATE: u32 = 44_100;
+
+/// What's played on a beat.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cue {
+    /// On pace, or not jumping at all.
+    Click,
+    SpeedUp,
+    SlowDown,
+}
+
+/// Generates mono audio samples of the metronome. It asks for the cadence on
+/// each beat.
+pub struct Metronome<F> {
+    // Called with the time since the start of the audio, returns the cadence
+    // in Hz.
+    cadence: F,
+    target_hz: f32,
+    tolerance_hz: f32,
+    // Samples generated so far.
+    sample_index: u64,
+    // Sample at which the next beat starts.
+    next_beat_at: u64,
+    // What's being played since which sample.
+    playing: Option<(Cue, u64)>,
+}
+
+/// Spawns a thread which plays the metronome on the default audio device,
+/// following the live cadence of the tracker.
+pub fn play(
+    target_bpm: f32,
+    tolerance_bpm: f32,
+    tracker: Arc<FrequencyTracker>,
+) -> AppResult<()> {
+    let metronome = Metronome::new(target_bpm, tolerance_bpm, move |_| {
+        tracker.calculate_latest()
+    });
+
+    // the output stream must outlive the playback, therefore we open it on a
+    // thread which never finishes, and report the result back
+    let (sender, receiver) = std::sync::mpsc::channel();
+    thread::spawn(move || {
+        let (_stream, handle) = match OutputStream::try_default() {
+            Ok(output) => output,
+            Err(e) => {
+                return sender
+                    .send(Err(AppError::Audio(e.to_string())))
+                    .unwrap()
+            }
+        };
+        let played = handle
+            .play_raw(metronome.convert_samples())
+            .map_err(|e| AppError::Audio(e.to_string()));
+        let failed = played.is_err();
+        sender.send(played).unwrap();
+
+        while !failed {
+            thread::park();
+        }
+    });
+
+    receiver.recv().map_err(Error::from)?
+}
+
+/// Renders the metronome for a recorded session into a WAV file, following
+/// the recorded consensus. Useful to hear what the athlete would have heard.
+pub fn render_trace(
+    trace: impl AsRef<Path>,
+    out: impl AsRef<Path>,
+    target_bpm: f32,
+    tolerance_bpm: f32,
+) -> Result<()> {
+    let consensus: Vec<_> = trace::read(trace)?
+        .into_iter()
+        .filter_map(|event| match event {
+            TraceEvent::Consensus { seconds, frequency } => {
+                Some((seconds, frequency))
+            }
+            _ => None,
+        })
+        .collect();
+    let duration = consensus.last().map(|(seconds, _)| *seconds).unwrap_or(0.0);
+
+    let metronome = Metronome::new(target_bpm, tolerance_bpm, |elapsed| {
+        let seconds = elapsed.as_secs_f64();
+        consensus
+            .iter()
+            .take_while(|(at, _)| *at <= seconds)
+            .last()
+            .and_then(|(_, frequency)| *frequency)
+    });
+    let samples_count = (duration * SAMPLE_RATE as f64) as usize;
+
+    write_wav(
+        BufWriter::new(File::create(out)?),
+        samples_count,
+        metronome.take(samples_count),
+    )
+}
+
+impl<F: FnMut(Duration) -> Option<f32>> Metronome<F> {
+    pub fn new(target_bpm: f32, tolerance_bpm: f32, cadence: F) -> Self {
+        Self {
+            cadence,
+            target_hz: target_bpm / 60.0,
+            tolerance_hz: tolerance_bpm / 60.0,
+            sample_index: 0,
+            next_beat_at: 0,
+            playing: None,
+        }
+    }
+
+    /// Which cue to play for given cadence.
+    pub fn cue(&self, cadence: Option<f32>) -> Cue {
+        match cadence {
+            Some(hz) if hz < self.target_hz - self.tolerance_hz => Cue::SpeedUp,
+            Some(hz) if hz > self.target_hz + self.tolerance_hz => {
+                Cue::SlowDown
+            }
+            _ => Cue::Click,
+        }
+    }
+
+    fn beat(&mut self) {
+        let elapsed = Duration::from_secs_f64(
+            self.sample_index as f64 / SAMPLE_RATE as f64,
+        );
+        let cadence = (self.cadence)(elapsed);
+        let cue = self.cue(cadence);
+        self.playing = Some((cue, self.sample_index));
+
+        let samples_per_beat = SAMPLE_RATE as f32 / self.target_hz;
+        self.next_beat_at += samples_per_beat.round() as u64;
+    }
+}
+
+impl<F: FnMut(Duration) -> Option<f32>> Iterator for Metronome<F> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.sample_index == self.next_beat_at {
+            self.beat();
+        }
+
+        let sample = match self.playing {
+            Some((cue, since)) => {
+                let t = (self.sample_index - since) as f32 / SAMPLE_RATE as f32;
+                let sample = cue.sample_at(t);
+                if sample.is_none() {
+                    self.playing = None;
+                }
+                sample.unwrap_or(0.0)
+            }
+            None => 0.0,
+        };
+        self.sample_index += 1;
+
+        Some(sample)
+    }
+}
+
+impl<F: FnMut(Duration) -> Option<f32>> Source for Metronome<F> {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl Cue {
+    // Returns the sample of the cue at given seconds since its start, or none
+    // if the cue is over.
+    fn sample_at(self, t: f32) -> Option<f32> {
+        let (hz, length) = match self {
+            Self::Click => (1000.0, 0.03),
+            Self::SpeedUp => (1600.0, 0.12),
+            Self::SlowDown => (500.0, 0.12),
+        };
+
+        if t >= length {
+            return None;
+        }
+
+        // linear fade out avoids a crack at the end
+        let envelope = 1.0 - t / length;
+        Some((2.0 * PI * hz * t).sin() * envelope * 0.8)
+    }
+}
+
+/// Writes mono 16 bit PCM samples in the range [-1; 1] as they are
+/// generated, so that long sessions don't need to fit into memory. The header
+/// comes first, therefore the count of the samples must be known upfront.
+fn write_wav(
+    mut out: impl Write,
+    samples_count: usize,
+    samples: impl Iterator<Item = f32>,
+) -> Result<()> {
+    let data_len = samples_count as u32 * 2;
+
+    out.write_all(b"RIFF")?;
+    out.write_all(&(36 + data_len).to_le_bytes())?;
+    out.write_all(b"WAVE")?;
+
+    out.write_all(b"fmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?; // PCM
+    out.write_all(&1u16.to_le_bytes())?; // channels
+    out.write_all(&SAMPLE_RATE.to_le_bytes())?;
+    out.write_all(&(SAMPLE_RATE * 2).to_le_bytes())?; // bytes per second
+    out.write_all(&2u16.to_le_bytes())?; // bytes per sample
+    out.write_all(&16u16.to_le_bytes())?; // bits per sample
+
+    out.write_all(b"data")?;
+    out.write_all(&data_len.to_le_bytes())?;
+    let mut written = 0;
+    for sample in samples {
+        let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        out.write_all(&sample.to_le_bytes())?;
+        written += 1;
+    }
+    // the header would be wrong otherwise
+    debug_assert_eq!(written, samples_count);
+
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Returns the sample indices at which cues start.
+    fn onsets(samples: &[f32]) -> Vec<usize> {
+        let mut onsets = vec![];
+        let mut silent_for = usize::MAX;
+        for (i, sample) in samples.iter().enumerate() {
+            if *sample != 0.0 && silent_for > 100 {
+                onsets.push(i);
+            }
+            silent_for = if *sample == 0.0 {
+                silent_for.saturating_add(1)
+            } else {
+                0
+            };
+        }
+        onsets
+    }
+
+    #[test]
+    fn it_clicks_at_target_and_cues_off_pace() {
+        // 150 BPM is a beat each 0.4s, the athlete slows down after 1s
+        let metronome = Metronome::new(150.0, 6.0, |elapsed| {
+            if elapsed < Duration::from_secs(1) {
+                Some(2.5)
+            } else {
+                Some(2.0)
+            }
+        });
+        assert_eq!(metronome.cue(Some(2.55)), Cue::Click);
+        assert_eq!(metronome.cue(Some(2.0)), Cue::SpeedUp);
+        assert_eq!(metronome.cue(Some(3.0)), Cue::SlowDown);
+        assert_eq!(metronome.cue(None), Cue::Click);
+
+        let samples: Vec<_> =
+            metronome.take(SAMPLE_RATE as usize * 2).collect();
+        let beat = (SAMPLE_RATE as f32 * 0.4) as usize;
+        // the first sample of a sine is zero
+        let expected: Vec<_> = (0..5).map(|n| n * beat + 1).collect();
+        assert_eq!(onsets(&samples), expected);
+
+        let sounding_after = |onset: usize| {
+            samples[onset..onset + beat]
+                .iter()
+                .rposition(|s| *s != 0.0)
+                .unwrap()
+        };
+        // clicks are short, the cue to speed up is long
+        assert!(sounding_after(beat + 1) < SAMPLE_RATE as usize / 20);
+        assert!(sounding_after(3 * beat + 1) > SAMPLE_RATE as usize / 10);
+    }
+
+    #[test]
+    fn it_writes_wav() {
+        let mut wav = vec![];
+        write_wav(&mut wav, 3, vec![0.0, 1.0, -1.0].into_iter()).unwrap();
+
+        assert_eq!(wav.len(), 44 + 6);
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(&wav[44..], &[0, 0, 0xff, 0x7f, 0x01, 0x80]);
+    }
+}
@@ -98,14 +98,7 @@ impl Oscillator {
         debug_assert_eq!(scratch_a.len(), window);
         debug_assert_eq!(scratch_b.len(), window);
 
-        // not enough data yet to find necessary range of frequencies
-        if window > self.state.len() {
-            return None;
-        }
-
-        // The values don't oscillate between distinct enough values. Lot of
-        // image noise causes slight changes of brightness. This filters it out.
-        if self.variance < 10.0 {
+        if !self.is_oscillating() {
             return None;
         }
 
@@ -121,6 +114,19 @@ impl Oscillator {
         largest_bin(window, relevant_bins, scratch_a.iter())
     }
 
+    /// Whether there's enough data and it varies enough to look for a
+    /// frequency in it.
+    pub fn is_oscillating(&self) -> bool {
+        // not enough data yet to find necessary range of frequencies
+        if self.window() > self.state.len() {
+            return false;
+        }
+
+        // The values don't oscillate between distinct enough values. Lot of
+        // image noise causes slight changes of brightness. This filters it out.
+        self.variance >= 10.0
+    }
+
     // Set the buffer to the tail of the state where the len of the tail is
     // given by window size.
     fn populate_buffer_with_state(&self, scratch_a: &mut [Complex<f32>]) {
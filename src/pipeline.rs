@@ -0,0 +1,315 @@
+use crate::analyzer::{self, Analyzer, AnalyzerBuilder, Report};
+use crate::frequency_tracker::FrequencyTracker;
+use crate::metrics::PipelineMetrics;
+use crate::prelude::*;
+use crate::preview::Preview;
+use crate::source::{Frame, FrameSource};
+use crate::spectrogram::Spectrogram;
+use rand::{rngs::StdRng, SeedableRng};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+
+/// Runs analyzers of all window sizes (see [`PipelineConfig`]), each on its
+/// own thread, and feeds their reports into the consensus.
+///
+/// Offline work, where frames can be read faster than real time, waits for
+/// each frame to be processed fully before the next one is pushed, see
+/// [`Pipeline::push_frame`]. That makes it deterministic. A live source
+/// doesn't wait for the analyzers, see [`Pipeline::send_frame`].
+pub struct Pipeline {
+    // Analyzers which haven't got any frame yet. They are moved to their
+    // threads with the first frame, see [`Pipeline::with_preview`].
+    analyzers: Vec<Analyzer>,
+    // The analyzers running on their threads, in the order of the windows.
+    workers: Vec<Worker>,
+    tracker: Arc<FrequencyTracker>,
+    // Index of the analysed source in the tracker, see
+    // [`Pipeline::with_tracker`].
+    source: usize,
+    // See [`Pipeline::subscribe`].
+    subscribers: Vec<Sender<Report>>,
+    // See [`Pipeline::metrics`].
+    metrics: Arc<PipelineMetrics>,
+}
+
+/// How the frames are analysed, see [`Pipeline::from_source`].
+#[derive(Debug, Clone)]
+pub struct PipelineConfig {
+    /// Window size of each analyzer in seconds of video, 4, 8 and 12 by
+    /// default. The longer the window, the finer the frequencies it tells
+    /// apart, but the slower it follows changes. There must be at least one,
+    /// and none of them can be zero.
+    pub window_multipliers: Vec<usize>,
+    /// The oscillators are placed randomly, the same seed places them the
+    /// same way, so that the same frames are analysed the same way.
+    pub seed: u64,
+}
+
+// An analyzer running on its own thread, see [`analyzer::channel`].
+struct Worker {
+    frames: Sender<Arc<Frame>>,
+    // one message per frame, with the report if the frame triggered any
+    reports: Receiver<Option<Report>>,
+    // frames sent to the analyzer which it hasn't processed yet
+    pending: usize,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            window_multipliers: WINDOW_MULTIPLIERS.to_vec(),
+            seed: 0,
+        }
+    }
+}
+
+impl Pipeline {
+    /// Analyses frames of the frame rate and the dimensions of the source,
+    /// e.g. to [`Self::run`] over it. Fails if the config has no windows or
+    /// an empty one, or if the frame rate is too low.
+    pub fn from_source(
+        frames: &dyn FrameSource,
+        config: &PipelineConfig,
+    ) -> Result<Self> {
+        let multipliers = &config.window_multipliers;
+        if multipliers.is_empty() || multipliers.contains(&0) {
+            return Err(Error::InvalidArgument(format!(
+                "Windows must be at least a second long, got {:?}",
+                multipliers
+            )));
+        }
+
+        let frame_rate = frames.frame_rate();
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let analyzers = multipliers
+            .iter()
+            .map(|multiplier| {
+                AnalyzerBuilder {
+                    frame_rate,
+                    window: frame_rate * multiplier,
+                    frame_width: frames.width(),
+                    frame_height: frames.height(),
+                }
+                .build(&mut rng)
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            analyzers,
+            workers: vec![],
+            tracker: Arc::new(FrequencyTracker::new(frame_rate)),
+            source: 0,
+            subscribers: vec![],
+            metrics: Arc::new(PipelineMetrics::new()),
+        })
+    }
+
+    /// Shows what the oscillators of the smallest window vote for in the
+    /// preview, e.g. to aim the camera, see [`Preview`]. Only takes effect
+    /// before the first frame is pushed.
+    pub fn with_preview(mut self, preview: Arc<Preview>) -> Self {
+        if !self.analyzers.is_empty() {
+            let smallest = self.analyzers.remove(0);
+            self.analyzers.insert(0, smallest.with_preview(preview));
+        }
+
+        self
+    }
+
+    /// Shares how the votes of all windows spread over frequencies with the
+    /// spectrogram, see [`Spectrogram`]. Only takes effect before the first
+    /// frame is pushed.
+    pub fn with_spectrogram(mut self, spectrogram: Arc<Spectrogram>) -> Self {
+        self.analyzers = self
+            .analyzers
+            .into_iter()
+            .map(|analyzer| analyzer.with_spectrogram(Arc::clone(&spectrogram)))
+            .collect();
+
+        self
+    }
+
+    /// Reports into a tracker shared with the pipelines of other sources, as
+    /// the source at given index, see [`FrequencyTracker::update_from`].
+    pub fn with_tracker(
+        mut self,
+        tracker: Arc<FrequencyTracker>,
+        source: usize,
+    ) -> Self {
+        self.tracker = tracker;
+        self.source = source;
+
+        self
+    }
+
+    /// Reports into metrics shared with the pipelines of other sources, e.g.
+    /// to serve them together, see [`PipelineMetrics::render`]. Only takes
+    /// effect before the first frame is pushed.
+    pub fn with_metrics(mut self, metrics: Arc<PipelineMetrics>) -> Self {
+        if self.workers.is_empty() {
+            self.metrics = metrics;
+        }
+
+        self
+    }
+
+    /// Receives every report from now on, e.g. to record them or to follow
+    /// the window sizes on another thread. Dropping the receiver
+    /// unsubscribes.
+    pub fn subscribe(&mut self) -> Receiver<Report> {
+        let (sender, receiver) = channel();
+        self.subscribers.push(sender);
+
+        receiver
+    }
+
+    /// Waits until all analyzers processed the frame. Updates the tracker
+    /// with any reports the frame produced, sends them to the subscribers,
+    /// and returns them.
+    pub fn push_frame(&mut self, frame: Arc<Frame>) -> Result<Vec<Report>> {
+        self.dispatch(frame)?;
+        self.collect(true)
+    }
+
+    /// Same as [`Self::push_frame`], but doesn't wait for the analyzers. The
+    /// reports are those of the frames the analyzers processed meanwhile.
+    ///
+    /// A live source shouldn't wait, if the analyzers fall behind, its
+    /// frames queue up for them instead of the source falling behind.
+    pub fn send_frame(&mut self, frame: Arc<Frame>) -> Result<Vec<Report>> {
+        self.dispatch(frame)?;
+        self.collect(false)
+    }
+
+    /// Pushes all frames of the source and ticks the tracker with each, see
+    /// [`FrequencyTracker::tick`]. Fails with the error the source stopped
+    /// with, if it didn't reach its end.
+    pub fn run(&mut self, frames: &mut dyn FrameSource) -> Result<()> {
+        for frame in &mut *frames {
+            let timestamp = frame.timestamp;
+            self.push_frame(Arc::new(frame))?;
+            self.tracker.tick(timestamp);
+        }
+
+        match frames.take_error() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Shared with whoever follows the cadence while the pipeline runs, e.g.
+    /// a server on another thread.
+    pub fn tracker(&self) -> &Arc<FrequencyTracker> {
+        &self.tracker
+    }
+
+    /// Health of the pipeline, e.g. how many frames wait for each analyzer.
+    pub fn metrics(&self) -> &Arc<PipelineMetrics> {
+        &self.metrics
+    }
+
+    // Sends the frame to each analyzer, spawning their threads with the
+    // first frame.
+    fn dispatch(&mut self, frame: Arc<Frame>) -> Result<()> {
+        let source = self.source;
+        let metrics = &self.metrics;
+        self.workers
+            .extend(self.analyzers.drain(..).map(|analyzer| {
+                let (frames, reports) =
+                    analyzer::channel(analyzer, source, metrics);
+                Worker {
+                    frames,
+                    reports,
+                    pending: 0,
+                }
+            }));
+
+        for worker in &mut self.workers {
+            worker.frames.send(Arc::clone(&frame))?;
+            worker.pending += 1;
+        }
+        self.metrics.frame_decoded();
+        self.metrics.frame_dispatched(source);
+
+        Ok(())
+    }
+
+    // Applies the reports of the frames the analyzers processed. Waits for
+    // all pending frames if asked to.
+    fn collect(&mut self, wait: bool) -> Result<Vec<Report>> {
+        let mut reports = vec![];
+        for worker in &mut self.workers {
+            while worker.pending > 0 {
+                let report = if wait {
+                    worker.reports.recv()?
+                } else {
+                    match worker.reports.try_recv() {
+                        Ok(report) => report,
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Disconnected) => {
+                            return Err(Error::ThreadStopped)
+                        }
+                    }
+                };
+                worker.pending -= 1;
+                reports.extend(report);
+            }
+        }
+
+        for report in &reports {
+            self.tracker.update_from(self.source, report.clone());
+            self.subscribers
+                .retain(|subscriber| subscriber.send(report.clone()).is_ok());
+        }
+
+        Ok(reports)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::CadenceScene;
+
+    #[test]
+    fn it_rejects_empty_windows() {
+        let frames = CadenceScene::constant(2.0).into_source(8, 6, 30);
+
+        for window_multipliers in &[vec![], vec![4, 0]] {
+            let config = PipelineConfig {
+                window_multipliers: window_multipliers.clone(),
+                seed: 0,
+            };
+            assert!(matches!(
+                Pipeline::from_source(&frames, &config),
+                Err(Error::InvalidArgument(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn it_keeps_metrics_of_each_pipeline() {
+        let frames = CadenceScene::constant(2.0).into_source(40, 30, 30);
+        let config = PipelineConfig::default();
+        let mut first = Pipeline::from_source(&frames, &config).unwrap();
+        let mut second = Pipeline::from_source(&frames, &config).unwrap();
+        // e.g. another camera of the same session
+        let mut shared = Pipeline::from_source(&frames, &config)
+            .unwrap()
+            .with_tracker(Arc::clone(first.tracker()), 1)
+            .with_metrics(Arc::clone(first.metrics()));
+
+        for frame in frames.take(3) {
+            let frame = Arc::new(frame);
+            first.push_frame(Arc::clone(&frame)).unwrap();
+            second.push_frame(Arc::clone(&frame)).unwrap();
+            shared.push_frame(frame).unwrap();
+        }
+
+        assert_eq!(first.metrics().frames_decoded.get(), 6);
+        assert_eq!(second.metrics().frames_decoded.get(), 3);
+        let rendered = first.metrics().render();
+        assert!(rendered.contains("{source=\"1\",window=\"120\"}"));
+        assert!(!second.metrics().render().contains("source=\"1\""));
+    }
+}
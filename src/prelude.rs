@@ -1,17 +1,36 @@
-use std::{error::Error as StdError, result::Result as StdResult};
+pub use crate::error::Error;
+use std::result::Result as StdResult;
 
-pub type Result<T> = StdResult<T, Box<dyn StdError>>;
+pub type Result<T> = StdResult<T, Error>;
 
 /// Ratio between the number of oscillators who agree on a frequency, and the
 /// total oscillators who identified any frequency.
 pub const MIN_OSCILLATORS_AGREEMENT_RATIO: f32 = 1.0 / 2.0;
 
-/// Size of the pixel square whose average value a single [`Oscillator`] tracks.
+/// Size of the pixel square whose average value a single oscillator tracks.
 pub const VIEW_SIZE: u32 = 2;
 
-/// Every n ms, frequency [`Analyzer`] reports current estimated frequency.
+/// Each analyzer runs FFT over a window of this many seconds of video.
+///
+/// The larger the multiplier, the more granular frequency intervals it can
+/// find. However, it takes longer to start reporting and it takes longer to
+/// adjust to rapid speed changes.
+///
+/// We therefore spawn multiple and let them reach a consensus.
+pub const WINDOW_MULTIPLIERS: &[usize] = &[4, 8, 12];
+
+/// Every n ms, each frequency analyzer reports current estimated frequency.
 pub const REPORT_FREQUENCY_AFTER_MS: usize = 250;
 
+/// Slower video cannot be analysed, as the analyzers report at most once per
+/// frame, see [`REPORT_FREQUENCY_AFTER_MS`].
+pub const MIN_FRAME_RATE: usize =
+    (1000 + REPORT_FREQUENCY_AFTER_MS - 1) / REPORT_FREQUENCY_AFTER_MS;
+
+/// If no analyzer reported for n ms, the athlete most likely stopped
+/// jumping and the latest consensus is no longer current.
+pub const STALE_REPORT_AFTER_MS: usize = 1500;
+
 /// Every n ms clean up work is done to avoid growing state buffers
 /// indefinitely.
 pub const TRUNCATE_STATE_AFTER_MS: usize = 2000;
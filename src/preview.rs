@@ -0,0 +1,147 @@
+//! What the analysis sees, so that the camera can be aimed.
+//!
+//! The latest frame is shown with the oscillators of one analyzer drawn on
+//! it. Each oscillator is coloured by the frequency it votes for, from red
+//! for the slowest cadence to violet for the fastest, or gray if it doesn't
+//! vote because its pixels don't vary enough or no frequency stands out.
+
+use crate::prelude::*;
+use crate::source::Frame;
+use image::GrayImage;
+use std::sync::{Arc, Mutex};
+
+/// Shared between the analysis which updates it, see
+/// [`Pipeline::with_preview`], and the UI which shows it.
+///
+/// [`Pipeline::with_preview`]: crate::pipeline::Pipeline::with_preview
+#[derive(Default)]
+pub struct Preview {
+    inner: Mutex<State>,
+}
+
+/// An oscillator, which follows the shade of a small square of the frame, and
+/// its vote in the latest report.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vote {
+    /// Top left corner of the square of pixels it tracks.
+    pub x: u32,
+    pub y: u32,
+    /// Frequency of the bin it votes for, if any.
+    pub hz: Option<f32>,
+}
+
+#[derive(Default)]
+struct State {
+    frame: Option<Arc<Frame>>,
+    votes: Arc<Vec<Vote>>,
+    // Incremented with each new frame, so that the UI can tell whether
+    // there's anything new to show.
+    version: usize,
+}
+
+impl Preview {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Shows the latest frame read. The pipeline only sets the votes, as the
+    /// frames may be read faster than it analyses them.
+    pub fn set_frame(&self, frame: Arc<Frame>) {
+        let mut state = self.inner.lock().unwrap();
+        state.frame = Some(frame);
+        state.version += 1;
+    }
+
+    pub(crate) fn set_votes(&self, votes: Vec<Vote>) {
+        self.inner.lock().unwrap().votes = Arc::new(votes);
+    }
+
+    /// The latest votes, even before the first frame.
+    pub fn votes(&self) -> Arc<Vec<Vote>> {
+        Arc::clone(&self.inner.lock().unwrap().votes)
+    }
+
+    /// The version of the preview, the latest frame and the latest votes.
+    /// None until the first frame.
+    pub fn latest(&self) -> Option<(usize, Arc<Frame>, Arc<Vec<Vote>>)> {
+        let state = self.inner.lock().unwrap();
+        let frame = Arc::clone(state.frame.as_ref()?);
+
+        Some((state.version, frame, Arc::clone(&state.votes)))
+    }
+}
+
+/// Draws the votes over the frame, which is darkened so that they stand out.
+/// Returns RGBA pixels row by row.
+pub fn paint(image: &GrayImage, votes: &[Vote]) -> Vec<u8> {
+    let (width, height) = image.dimensions();
+    let mut rgba = Vec::with_capacity(image.len() * 4);
+    for pixel in image.pixels() {
+        let shade = pixel.0[0] / 2;
+        rgba.extend_from_slice(&[shade, shade, shade, 255]);
+    }
+
+    for vote in votes {
+        let color = vote.hz.map(hz_to_color).unwrap_or([200, 200, 200]);
+        for y in vote.y..(vote.y + VIEW_SIZE).min(height) {
+            for x in vote.x..(vote.x + VIEW_SIZE).min(width) {
+                let at = (y * width + x) as usize * 4;
+                rgba[at..at + 3].copy_from_slice(&color);
+            }
+        }
+    }
+
+    rgba
+}
+
+// Hue from red for the lowest frequency of interest to violet for the highest.
+fn hz_to_color(hz: f32) -> [u8; 3] {
+    let range = HIGHEST_FREQUENCY_OF_INTEREST - LOWEST_FREQUENCY_OF_INTEREST;
+    let progress = ((hz - LOWEST_FREQUENCY_OF_INTEREST) / range).clamp(0., 1.);
+
+    // the hue circle in six sectors with full saturation and value
+    let hue = progress * 270.0 / 60.0;
+    let rising = hue.fract();
+    let (r, g, b) = match hue as usize {
+        0 => (1.0, rising, 0.0),
+        1 => (1.0 - rising, 1.0, 0.0),
+        2 => (0.0, 1.0, rising),
+        3 => (0.0, 1.0 - rising, 1.0),
+        _ => (rising, 0.0, 1.0),
+    };
+    let byte = |value: f32| (value * 255.0).round() as u8;
+
+    [byte(r), byte(g), byte(b)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    #[test]
+    fn it_paints_votes_over_frame() {
+        let image = GrayImage::from_pixel(4, 3, Luma([100]));
+        let votes = [
+            Vote {
+                x: 0,
+                y: 0,
+                hz: Some(LOWEST_FREQUENCY_OF_INTEREST),
+            },
+            // clipped by the frame edge
+            Vote {
+                x: 3,
+                y: 2,
+                hz: None,
+            },
+        ];
+
+        let rgba = paint(&image, &votes);
+        let pixel = |x: usize, y: usize| &rgba[(y * 4 + x) * 4..][..4];
+        assert_eq!(pixel(1, 1), &[255, 0, 0, 255]);
+        assert_eq!(pixel(2, 1), &[50, 50, 50, 255]);
+        assert_eq!(pixel(3, 2), &[200, 200, 200, 255]);
+
+        assert_eq!(hz_to_color(HIGHEST_FREQUENCY_OF_INTEREST), [128, 0, 255]);
+    }
+}
@@ -0,0 +1,81 @@
+//! Drawing onto RGB images, for the annotated video and the exported graph.
+//!
+//! Coordinates are in pixels from the top left corner and may be fractional.
+//! Anything outside of the image is clipped.
+
+use crate::glyphs;
+use image::{Rgb, RgbImage};
+
+/// Blends the color over the rectangle given by its top left corner and size.
+/// At opacity 1.0 the color covers the image completely.
+pub fn fill_rect(
+    image: &mut RgbImage,
+    corner: (f32, f32),
+    size: (f32, f32),
+    color: Rgb<u8>,
+    opacity: f32,
+) {
+    let (width, height) = image.dimensions();
+    let xs = corner.0.max(0.0) as u32..((corner.0 + size.0) as u32).min(width);
+    let ys = corner.1.max(0.0) as u32..((corner.1 + size.1) as u32).min(height);
+
+    for y in ys {
+        for x in xs.clone() {
+            let pixel = image.get_pixel_mut(x, y);
+            for (channel, color) in pixel.0.iter_mut().zip(&color.0) {
+                let blended =
+                    *channel as f32 * (1.0 - opacity) + *color as f32 * opacity;
+                *channel = blended.round() as u8;
+            }
+        }
+    }
+}
+
+/// Draws the text with the stroke font, see [`glyphs`], starting at given
+/// point on the baseline.
+pub fn draw_text(
+    image: &mut RgbImage,
+    text: &str,
+    at: (f32, f32),
+    height: f32,
+    color: Rgb<u8>,
+) {
+    for polyline in glyphs::polylines(text, height) {
+        // the glyphs have y going up, the image down
+        let points: Vec<_> =
+            polyline.iter().map(|(x, y)| (at.0 + x, at.1 - y)).collect();
+        for segment in points.windows(2) {
+            draw_line(image, segment[0], segment[1], height / 8.0, color);
+        }
+    }
+}
+
+/// Stamps squares of given thickness along the line.
+pub fn draw_line(
+    image: &mut RgbImage,
+    from: (f32, f32),
+    to: (f32, f32),
+    thickness: f32,
+    color: Rgb<u8>,
+) {
+    let (width, height) = image.dimensions();
+    let length = (to.0 - from.0).hypot(to.1 - from.1);
+    // two stamps per pixel so that there are no gaps
+    let steps = (length * 2.0).ceil().max(1.0) as usize;
+    let half = thickness / 2.0;
+
+    for step in 0..=steps {
+        let progress = step as f32 / steps as f32;
+        let x = from.0 + (to.0 - from.0) * progress;
+        let y = from.1 + (to.1 - from.1) * progress;
+
+        let xs = (x - half).max(0.0) as u32..((x + half) as u32 + 1).min(width);
+        let ys =
+            (y - half).max(0.0) as u32..((y + half) as u32 + 1).min(height);
+        for y in ys {
+            for x in xs.clone() {
+                image.put_pixel(x, y, color);
+            }
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! How steady the cadence is, as opposed to how fast it is.
+//!
+//! The statistics are calculated over the consensus which follows each
+//! [`analyzer::Report`], and each set of jumps is evaluated on its own.
+//!
+//! [`analyzer::Report`]: crate::analyzer::Report
+
+use serde::Serialize;
+use std::time::Duration;
+
+/// Variability is calculated over this many most recent seconds, and so is the
+/// cadence which fatigue compares to the peak.
+pub const ROLLING_WINDOW_SECONDS: u64 = 10;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct RhythmStats {
+    /// Standard deviation of the cadence relative to its mean over the
+    /// rolling window, e.g. 0.05 if the cadence swings by about 5 %.
+    pub variability: Option<f32>,
+    /// How many Hz the cadence gains each minute of the set, negative when
+    /// the athlete slows down. It's the slope of a line fitted through the
+    /// whole set.
+    pub drift_hz_per_minute: Option<f32>,
+    /// How far the cadence over the rolling window declined from its peak in
+    /// the set, relative to the peak. Zero when jumping at the peak.
+    pub fatigue: Option<f32>,
+}
+
+/// Collects the consensus of the current set.
+#[derive(Debug, Clone, Default)]
+pub struct Rhythm {
+    // Consensus in Hz since the start of the set, in order of time.
+    samples: Vec<(Duration, f32)>,
+    // The highest mean of the rolling window in the set.
+    peak: Option<f32>,
+}
+
+impl Rhythm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the consensus at given time. Replaces the previous one if it was
+    /// at the same time, as several windows report with the same frame.
+    pub fn push(&mut self, at: Duration, hz: f32) {
+        match self.samples.last_mut() {
+            Some(last) if last.0 == at => last.1 = hz,
+            _ => self.samples.push((at, hz)),
+        }
+
+        let rolling_mean = mean(self.rolling());
+        self.peak = match self.peak {
+            Some(peak) if peak >= rolling_mean => Some(peak),
+            _ => Some(rolling_mean),
+        };
+    }
+
+    /// Forgets the consensus before given time, which is when a new set
+    /// started.
+    pub fn start_set(&mut self, at: Duration) {
+        self.samples.retain(|(t, _)| *t >= at);
+        self.peak = None;
+        // the peak must only consider the rolling windows within the set
+        for (at, hz) in std::mem::take(&mut self.samples) {
+            self.push(at, hz);
+        }
+    }
+
+    pub fn stats(&self) -> RhythmStats {
+        let rolling = self.rolling();
+        if rolling.is_empty() {
+            return RhythmStats::default();
+        }
+
+        let rolling_mean = mean(rolling);
+        RhythmStats {
+            variability: Some(std_deviation(rolling) / rolling_mean)
+                .filter(|_| rolling.len() > 1),
+            drift_hz_per_minute: slope(&self.samples).map(|s| s * 60.0),
+            fatigue: self.peak.map(|peak| 1.0 - rolling_mean / peak),
+        }
+    }
+
+    // Samples within the rolling window before the latest one.
+    fn rolling(&self) -> &[(Duration, f32)] {
+        let latest = match self.samples.last() {
+            Some((latest, _)) => *latest,
+            None => return &[],
+        };
+        let since =
+            latest.saturating_sub(Duration::from_secs(ROLLING_WINDOW_SECONDS));
+        let start = self.samples.iter().position(|(t, _)| *t >= since);
+
+        &self.samples[start.unwrap_or_default()..]
+    }
+}
+
+fn mean(samples: &[(Duration, f32)]) -> f32 {
+    samples.iter().map(|(_, hz)| hz).sum::<f32>() / samples.len() as f32
+}
+
+fn std_deviation(samples: &[(Duration, f32)]) -> f32 {
+    let mean = mean(samples);
+    let variance = samples
+        .iter()
+        .map(|(_, hz)| (hz - mean).powi(2))
+        .sum::<f32>()
+        / samples.len() as f32;
+
+    variance.sqrt()
+}
+
+// Least squares slope in Hz per second, none if all samples are at the same
+// time.
+fn slope(samples: &[(Duration, f32)]) -> Option<f32> {
+    let n = samples.len() as f32;
+    let mean_t = samples.iter().map(|(t, _)| t.as_secs_f32()).sum::<f32>() / n;
+    let mean_hz = mean(samples);
+
+    let (mut covariance, mut variance) = (0.0, 0.0);
+    for (t, hz) in samples {
+        let dt = t.as_secs_f32() - mean_t;
+        covariance += dt * (hz - mean_hz);
+        variance += dt * dt;
+    }
+
+    if variance > 0.0 {
+        Some(covariance / variance)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_measures_steadiness_and_fatigue() {
+        // steady at 3Hz for 20s, then slowing down by 0.5Hz per minute
+        let mut rhythm = Rhythm::new();
+        for tick in 0..(80 * 4) {
+            let seconds = tick as f32 * 0.25;
+            let hz = 3.0 - (seconds - 20.0).max(0.0) * 0.5 / 60.0;
+            rhythm.push(Duration::from_secs_f32(seconds), hz);
+        }
+        let stats = rhythm.stats();
+        let fatigue = stats.fatigue.unwrap();
+        // the rolling window is centered around 75s, 55s into the decline
+        assert!((fatigue - 0.46 / 3.0).abs() < 0.01, "{}", fatigue);
+        assert!(stats.drift_hz_per_minute.unwrap() < -0.3);
+        assert!(stats.variability.unwrap() < 0.01);
+
+        // the new set is steady, though the cadence alternates
+        rhythm.start_set(Duration::from_secs(80));
+        for tick in 0..(20 * 4) {
+            let hz = if tick % 2 == 0 { 2.4 } else { 2.6 };
+            rhythm.push(Duration::from_millis(80_000 + tick * 250), hz);
+        }
+        let stats = rhythm.stats();
+        assert!((stats.variability.unwrap() - 0.04).abs() < 0.001);
+        assert!(stats.drift_hz_per_minute.unwrap().abs() < 0.05);
+        assert!(stats.fatigue.unwrap().abs() < 0.01);
+    }
+}
@@ -0,0 +1,247 @@
+//! Publishes the live cadence to other apps on the local network.
+//!
+//! - `GET /snapshot` responds with the latest [`Snapshot`] as JSON.
+//! - `GET /ws` upgrades to a WebSocket which pushes the latest [`Snapshot`]
+//!   every [`REPORT_FREQUENCY_AFTER_MS`].
+//!
+//!
+//! The health of the pipeline is served separately, see [`serve_metrics`].
+//!
+//! [`Snapshot`]: jump_rope::Snapshot
+
+use crate::app_error::{AppError, AppResult};
+use jump_rope::frequency_tracker::FrequencyTracker;
+use jump_rope::metrics::PipelineMetrics;
+use jump_rope::REPORT_FREQUENCY_AFTER_MS;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tungstenite::{Message, WebSocket};
+
+// Longer request lines are rejected.
+const MAX_REQUEST_LINE: usize = 2048;
+// How long a client may take to send the request line, and to send more of
+// the request at all. A client which sends nothing is disconnected after
+// that, rather than tying up its thread.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Binds to the address and spawns a thread which serves each connection on
+/// its own thread. Returns the bound address.
+pub fn start(
+    addr: impl ToSocketAddrs,
+    tracker: Arc<FrequencyTracker>,
+) -> AppResult<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("Cannot accept connection: {}", e);
+                    continue;
+                }
+            };
+
+            let tracker = Arc::clone(&tracker);
+            thread::spawn(move || {
+                if let Err(e) = handle(stream, &tracker) {
+                    eprintln!("Connection failed: {}", e);
+                }
+            });
+        }
+    });
+
+    Ok(local_addr)
+}
+
+/// Spawns a thread which serves the metrics of the pipelines on given address
+/// in the Prometheus text format, see [`PipelineMetrics::render`]. Returns
+/// the bound address.
+pub fn serve_metrics(
+    addr: impl ToSocketAddrs,
+    metrics: Arc<PipelineMetrics>,
+) -> AppResult<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming().filter_map(|stream| stream.ok()) {
+            let rendered = metrics.render();
+            let content_type = "text/plain; version=0.0.4";
+            if let Err(e) = respond(stream, "200 OK", content_type, &rendered) {
+                eprintln!("Cannot serve metrics: {}", e);
+            }
+        }
+    });
+
+    Ok(local_addr)
+}
+
+fn handle(stream: TcpStream, tracker: &FrequencyTracker) -> AppResult<()> {
+    // the connection closes with any error, e.g. when the timeout hits
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let request_line = peek_request_line(&stream)?;
+    // e.g. "GET /snapshot?pretty HTTP/1.1"
+    let target = request_line.split_whitespace().nth(1).unwrap_or("/");
+    let path = target.split('?').next().unwrap_or(target);
+
+    match path {
+        "/ws" => push_snapshots(tungstenite::accept(stream)?, tracker),
+        "/snapshot" => {
+            let snapshot = serde_json::to_string(&tracker.snapshot())?;
+            respond(stream, "200 OK", "application/json", &snapshot)
+        }
+        _ => respond(stream, "404 Not Found", "text/plain", "Not found"),
+    }
+}
+
+// We only peek at the request line, because the WebSocket handshake reads
+// the request itself. The line may arrive in several packets.
+fn peek_request_line(stream: &TcpStream) -> AppResult<String> {
+    let mut buf = [0; MAX_REQUEST_LINE];
+    let deadline = Instant::now() + REQUEST_TIMEOUT;
+    let timed_out = || {
+        AppError::BadRequest("Timed out waiting for the request".to_string())
+    };
+
+    loop {
+        let len = match stream.peek(&mut buf) {
+            Ok(len) => len,
+            // which of the two depends on the platform
+            Err(e)
+                if e.kind() == ErrorKind::WouldBlock
+                    || e.kind() == ErrorKind::TimedOut =>
+            {
+                return Err(timed_out())
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let peeked = &buf[..len];
+        if let Some(end) = peeked.windows(2).position(|w| w == b"\r\n") {
+            return Ok(String::from_utf8_lossy(&peeked[..end]).into_owned());
+        }
+
+        if len == 0 {
+            return Err(AppError::BadRequest(
+                "Connection closed before the request".to_string(),
+            ));
+        } else if len == buf.len() {
+            return Err(AppError::BadRequest(
+                "Request line is too long".to_string(),
+            ));
+        } else if Instant::now() > deadline {
+            return Err(timed_out());
+        }
+        // peeking again returns at once while the rest hasn't arrived
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn push_snapshots(
+    mut socket: WebSocket<TcpStream>,
+    tracker: &FrequencyTracker,
+) -> AppResult<()> {
+    loop {
+        let snapshot = serde_json::to_string(&tracker.snapshot())?;
+        if socket.write_message(Message::Text(snapshot)).is_err() {
+            // client disconnected
+            return Ok(());
+        }
+
+        thread::sleep(Duration::from_millis(REPORT_FREQUENCY_AFTER_MS as u64));
+    }
+}
+
+// Writes a complete HTTP response and closes the connection.
+fn respond(
+    mut stream: TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> AppResult<()> {
+    // read the rest of the request, otherwise closing the connection with
+    // unread data resets it before the client reads the response
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && line.trim() != "" {
+        line.clear();
+    }
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\n\
+         Content-Type: {}\r\n\
+         Content-Length: {}\r\n\
+         Access-Control-Allow-Origin: *\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path)
+            .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn it_serves_snapshot() {
+        let tracker = Arc::new(FrequencyTracker::new(30));
+        let addr = start("127.0.0.1:0", tracker).unwrap();
+
+        let response = get(addr, "/snapshot");
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.ends_with(
+            r#"{"frequency":null,"bpm":null,"confidence":null,"jump_count":0.0,"activity":"idle","trips":0,"rhythm":{"variability":null,"drift_hz_per_minute":null,"fatigue":null},"windows":[],"source":{"state":"running"}}"#
+        ));
+
+        let response = get(addr, "/snapshot?pretty");
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+
+        // the request line is split over several packets
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET /snap").unwrap();
+        stream.flush().unwrap();
+        thread::sleep(Duration::from_millis(50));
+        write!(stream, "shot HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+
+        let response = get(addr, "/nothing");
+        assert!(response.starts_with("HTTP/1.1 404"), "{}", response);
+
+        // a client which sends nothing is disconnected
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert_eq!(response, "");
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let url = format!("ws://{}/ws", addr);
+        let (mut socket, _) =
+            tungstenite::client(url.as_str(), stream).unwrap();
+        let message = socket.read_message().unwrap();
+        assert!(message.into_text().unwrap().contains("\"jump_count\":0.0"));
+    }
+}
@@ -0,0 +1,123 @@
+//! Anything the analysis can read video frames from.
+//!
+//! The pipeline only ever needs grayscale frames of constant dimensions at a
+//! known frame rate. Where they come from (a camera, a file, a directory of
+//! images, a pipe or a generator) is hidden behind [`FrameSource`].
+
+mod cadence;
+mod image_sequence;
+mod raw;
+mod synthetic;
+
+pub use cadence::CadenceScene;
+pub use image_sequence::ImageSequence;
+pub use raw::RawFrames;
+pub use synthetic::Synthetic;
+
+use crate::prelude::*;
+use image::GrayImage;
+use std::time::Duration;
+
+/// A single grayscale video frame.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// Time since the first frame of the source.
+    pub timestamp: Duration,
+    pub image: GrayImage,
+}
+
+/// Yields frames in order. All frames of a source have the same dimensions.
+pub trait FrameSource: Iterator<Item = Frame> {
+    /// How many frames per second the source yields.
+    fn frame_rate(&self) -> usize;
+
+    fn width(&self) -> u32;
+
+    fn height(&self) -> u32;
+
+    /// Why the source stopped yielding frames, if it didn't simply reach its
+    /// end, e.g. the camera was unplugged.
+    fn take_error(&mut self) -> Option<Error> {
+        None
+    }
+}
+
+/// Sources which don't carry their own timing information (image sequences,
+/// raw frames, generated frames) space frames evenly by the frame rate.
+fn timestamp_of(frame_index: usize, frame_rate: usize) -> Duration {
+    Duration::from_secs_f64(frame_index as f64 / frame_rate as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+    use std::fs;
+    use std::io::Cursor;
+
+    #[test]
+    fn it_generates_synthetic_frames() {
+        let frames: Vec<_> = Synthetic::new(4, 2, 10, |timestamp, image| {
+            let shade = (timestamp.as_secs_f32() * 100.0) as u8;
+            image.put_pixel(0, 0, Luma([shade]));
+        })
+        .take(5)
+        .collect();
+
+        assert_eq!(frames.len(), 5);
+        for (index, frame) in frames.iter().enumerate() {
+            assert_eq!(
+                frame.timestamp,
+                Duration::from_millis(index as u64 * 100)
+            );
+            assert_eq!(frame.image.dimensions(), (4, 2));
+            assert_eq!(frame.image[(0, 0)].0[0], index as u8 * 10);
+        }
+    }
+
+    #[test]
+    fn it_reads_raw_frames() {
+        // two and a half frames of 3x2 pixels
+        let bytes: Vec<u8> = (0..15).collect();
+        let frames: Vec<_> =
+            RawFrames::new(Cursor::new(bytes), 3, 2, 25).collect();
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].timestamp, Duration::from_millis(40));
+        assert_eq!(frames[1].image[(0, 0)].0[0], 6);
+        assert_eq!(frames[1].image[(2, 1)].0[0], 11);
+    }
+
+    #[test]
+    fn it_reads_image_sequence_in_numeric_order() {
+        let dir = std::env::temp_dir().join("jump_rope_image_sequence_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        for n in [1u8, 2, 10] {
+            let image = GrayImage::from_pixel(8, 6, Luma([n]));
+            image.save(dir.join(format!("frame_{}.png", n))).unwrap();
+        }
+        fs::write(dir.join("notes.txt"), "not a frame").unwrap();
+        // the sequence ends with an error at a frame of another size
+        GrayImage::new(4, 3).save(dir.join("frame_11.png")).unwrap();
+        GrayImage::new(8, 6).save(dir.join("frame_12.png")).unwrap();
+
+        let mut frames = ImageSequence::from_dir(&dir, 30).unwrap();
+        assert_eq!((frames.width(), frames.height()), (8, 6));
+
+        let shades: Vec<_> =
+            frames.by_ref().map(|f| f.image[(0, 0)].0[0]).collect();
+        assert_eq!(shades, vec![1, 2, 10]);
+        assert!(matches!(
+            frames.take_error(),
+            Some(Error::FrameSizeMismatch {
+                expected: (8, 6),
+                actual: (4, 3),
+            })
+        ));
+        assert!(frames.next().is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
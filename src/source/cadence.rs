@@ -0,0 +1,111 @@
+use super::Synthetic;
+use image::{GrayImage, Luma};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::f32::consts::PI;
+use std::time::Duration;
+
+const BACKGROUND_SHADE: u8 = 40;
+const JUMPER_SHADE: u8 = 110;
+const ROPE_SHADE: u8 = 10;
+
+/// Renders a crude jump roping scene with a known ground truth cadence: a
+/// bright block (the jumper) bouncing up and down and a dark horizontal bar
+/// (the rope) sweeping around it, both once per jump.
+///
+/// The cadence can change over time, and each pixel can have uniform noise
+/// added to it to resemble a cheap camera.
+pub struct CadenceScene {
+    // Jumps per second at given time since the start of the video.
+    cadence: Box<dyn Fn(Duration) -> f32 + Send>,
+    // Each pixel is shifted by a random value in the range of +- noise.
+    noise: u8,
+    rng: StdRng,
+    // The cadence is integrated into phase, so that changing cadence doesn't
+    // make the shapes jump around.
+    phase: f32,
+    last_timestamp: Duration,
+}
+
+impl CadenceScene {
+    pub fn new(cadence: impl Fn(Duration) -> f32 + Send + 'static) -> Self {
+        Self {
+            cadence: Box::new(cadence),
+            noise: 0,
+            rng: StdRng::seed_from_u64(0),
+            phase: 0.0,
+            last_timestamp: Duration::default(),
+        }
+    }
+
+    pub fn constant(hz: f32) -> Self {
+        Self::new(move |_| hz)
+    }
+
+    pub fn with_noise(mut self, noise: u8) -> Self {
+        self.noise = noise;
+        self
+    }
+
+    /// The noise is random but deterministic given the seed.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// The ground truth cadence in Hz at given time.
+    pub fn cadence_at(&self, timestamp: Duration) -> f32 {
+        (self.cadence)(timestamp)
+    }
+
+    pub fn into_source(
+        mut self,
+        width: u32,
+        height: u32,
+        frame_rate: usize,
+    ) -> Synthetic<impl FnMut(Duration, &mut GrayImage)> {
+        Synthetic::new(width, height, frame_rate, move |timestamp, image| {
+            self.render(timestamp, image)
+        })
+    }
+
+    /// Frames must be rendered in order of their timestamps.
+    pub fn render(&mut self, timestamp: Duration, image: &mut GrayImage) {
+        let dt = timestamp.saturating_sub(self.last_timestamp);
+        self.phase += 2.0 * PI * self.cadence_at(timestamp) * dt.as_secs_f32();
+        self.last_timestamp = timestamp;
+
+        let (width, height) = (image.width() as f32, image.height() as f32);
+
+        // the jumper is in the middle of the frame and in the air half of the
+        // time
+        let lift = height / 8.0 * (1.0 - self.phase.cos()) / 2.0;
+        let jumper_x = (width * 0.3)..(width * 0.7);
+        let jumper_y = (height * 0.3 - lift)..(height * 0.8 - lift);
+
+        // the rope goes around the jumper and is seen from the side, so it
+        // only moves up and down
+        let rope_y = height * 0.55 + height * 0.4 * self.phase.cos();
+        let rope_y = (rope_y - height / 40.0)..(rope_y + height / 40.0);
+        let rope_x = (width * 0.2)..(width * 0.8);
+
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let (x, y) = (x as f32, y as f32);
+            let shade = if rope_x.contains(&x) && rope_y.contains(&y) {
+                ROPE_SHADE
+            } else if jumper_x.contains(&x) && jumper_y.contains(&y) {
+                JUMPER_SHADE
+            } else {
+                BACKGROUND_SHADE
+            };
+
+            let noise = if self.noise > 0 {
+                let noise = self.noise as i16;
+                self.rng.gen_range(-noise..=noise)
+            } else {
+                0
+            };
+
+            *pixel = Luma([(shade as i16 + noise).clamp(0, 255) as u8]);
+        }
+    }
+}
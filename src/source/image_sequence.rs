@@ -0,0 +1,129 @@
+use super::{timestamp_of, Frame, FrameSource};
+use crate::prelude::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Reads a directory of numbered frames, e.g. `frame_0001.png`,
+/// `frame_0002.png` etc. PNG and JPEG images are supported.
+///
+/// Files are ordered by the number in their name, not lexicographically, so
+/// that `frame_10.png` comes after `frame_9.png`.
+pub struct ImageSequence {
+    // Images which are yet to be read, in reverse order so that we can pop.
+    paths: Vec<PathBuf>,
+    frame_rate: usize,
+    width: u32,
+    height: u32,
+    frame_index: usize,
+    // why the sequence ended early, e.g. a frame which cannot be read
+    error: Option<Error>,
+}
+
+impl ImageSequence {
+    /// Image files don't carry any timing information, hence the frame rate
+    /// must be provided.
+    pub fn from_dir(dir: impl AsRef<Path>, frame_rate: usize) -> Result<Self> {
+        let mut paths: Vec<_> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| is_supported_image(path))
+            .collect();
+        paths.sort_by_key(|path| frame_number(path));
+        paths.reverse();
+
+        // all frames must be of the same size as the first one
+        let first = paths.last().ok_or_else(|| {
+            Error::InvalidArgument("No PNG or JPEG frames found".to_string())
+        })?;
+        let (width, height) = image::image_dimensions(first)?;
+
+        Ok(Self {
+            paths,
+            frame_rate,
+            width,
+            height,
+            frame_index: 0,
+            error: None,
+        })
+    }
+}
+
+impl ImageSequence {
+    // Ends the sequence, the remaining frames would leave a gap in time.
+    fn stop(&mut self, error: Error) -> Option<Frame> {
+        self.paths.clear();
+        self.error = Some(error);
+        None
+    }
+}
+
+impl Iterator for ImageSequence {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let path = self.paths.pop()?;
+        let image = match image::open(&path) {
+            Ok(image) => image.into_luma8(),
+            Err(e) => {
+                let message = format!("Cannot read frame {:?}: {}", path, e);
+                return self.stop(Error::MalformedFile(message));
+            }
+        };
+
+        if image.dimensions() != (self.width, self.height) {
+            return self.stop(Error::FrameSizeMismatch {
+                expected: (self.width, self.height),
+                actual: image.dimensions(),
+            });
+        }
+
+        let timestamp = timestamp_of(self.frame_index, self.frame_rate);
+        self.frame_index += 1;
+
+        Some(Frame { timestamp, image })
+    }
+}
+
+impl FrameSource for ImageSequence {
+    fn frame_rate(&self) -> usize {
+        self.frame_rate
+    }
+
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn take_error(&mut self) -> Option<Error> {
+        self.error.take()
+    }
+}
+
+fn is_supported_image(path: &Path) -> bool {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+
+    matches!(
+        extension.as_deref(),
+        Some("png") | Some("jpg") | Some("jpeg")
+    )
+}
+
+// The last group of digits in the file stem. Files without any number sort
+// first.
+fn frame_number(path: &Path) -> Option<u64> {
+    let stem = path.file_stem()?.to_str()?;
+    let digits: String = stem
+        .chars()
+        .rev()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    let digits: String = digits.chars().rev().collect();
+
+    digits.parse().ok()
+}
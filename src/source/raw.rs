@@ -0,0 +1,64 @@
+use super::{timestamp_of, Frame, FrameSource};
+use image::GrayImage;
+use std::io::{self, Read};
+
+/// Reads raw 8 bit grayscale frames, one after another without any header,
+/// from a byte stream. This is what e.g.
+/// `ffmpeg -i video.mp4 -f rawvideo -pix_fmt gray -` writes to stdout.
+pub struct RawFrames<R> {
+    reader: R,
+    frame_rate: usize,
+    width: u32,
+    height: u32,
+    frame_index: usize,
+}
+
+impl RawFrames<io::Stdin> {
+    pub fn from_stdin(width: u32, height: u32, frame_rate: usize) -> Self {
+        Self::new(io::stdin(), width, height, frame_rate)
+    }
+}
+
+impl<R: Read> RawFrames<R> {
+    /// Raw frames don't carry any metadata, hence dimensions and frame rate
+    /// must be provided.
+    pub fn new(reader: R, width: u32, height: u32, frame_rate: usize) -> Self {
+        Self {
+            reader,
+            frame_rate,
+            width,
+            height,
+            frame_index: 0,
+        }
+    }
+}
+
+impl<R: Read> Iterator for RawFrames<R> {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut bytes = vec![0; self.width as usize * self.height as usize];
+        // a partially written frame at the end of the stream is dropped
+        self.reader.read_exact(&mut bytes).ok()?;
+
+        let image = GrayImage::from_raw(self.width, self.height, bytes)?;
+        let timestamp = timestamp_of(self.frame_index, self.frame_rate);
+        self.frame_index += 1;
+
+        Some(Frame { timestamp, image })
+    }
+}
+
+impl<R: Read> FrameSource for RawFrames<R> {
+    fn frame_rate(&self) -> usize {
+        self.frame_rate
+    }
+
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+}
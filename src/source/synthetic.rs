@@ -0,0 +1,93 @@
+use super::{timestamp_of, Frame, FrameSource};
+use image::GrayImage;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Generates frames by calling a render function with the timestamp of each
+/// frame and a black image to draw into.
+///
+/// Useful to run the analysis without any camera or footage. Unless
+/// [`Synthetic::with_duration`] is called, the source never ends.
+pub struct Synthetic<F> {
+    render: F,
+    frame_rate: usize,
+    width: u32,
+    height: u32,
+    frame_index: usize,
+    duration: Option<Duration>,
+    // If set, frames are not yielded sooner than their timestamp after this
+    // instant.
+    paced_since: Option<Option<Instant>>,
+}
+
+impl<F> Synthetic<F>
+where
+    F: FnMut(Duration, &mut GrayImage),
+{
+    pub fn new(width: u32, height: u32, frame_rate: usize, render: F) -> Self {
+        Self {
+            render,
+            frame_rate,
+            width,
+            height,
+            frame_index: 0,
+            duration: None,
+            paced_since: None,
+        }
+    }
+
+    /// Stops yielding frames once their timestamp reaches the duration.
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    /// Yields frames no faster than a camera would, which is what the live
+    /// view expects. Without pacing, frames are generated as fast as possible.
+    pub fn paced(mut self) -> Self {
+        self.paced_since = Some(None);
+        self
+    }
+}
+
+impl<F> Iterator for Synthetic<F>
+where
+    F: FnMut(Duration, &mut GrayImage),
+{
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let timestamp = timestamp_of(self.frame_index, self.frame_rate);
+        if matches!(self.duration, Some(d) if timestamp >= d) {
+            return None;
+        }
+
+        if let Some(paced_since) = &mut self.paced_since {
+            let due = *paced_since.get_or_insert_with(Instant::now) + timestamp;
+            thread::sleep(due.saturating_duration_since(Instant::now()));
+        }
+
+        let mut image = GrayImage::new(self.width, self.height);
+        (self.render)(timestamp, &mut image);
+        self.frame_index += 1;
+
+        Some(Frame { timestamp, image })
+    }
+}
+
+impl<F> FrameSource for Synthetic<F>
+where
+    F: FnMut(Duration, &mut GrayImage),
+{
+    fn frame_rate(&self) -> usize {
+        self.frame_rate
+    }
+
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+}
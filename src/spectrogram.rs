@@ -0,0 +1,129 @@
+//! How the votes of the oscillators spread over frequencies, for each window
+//! size.
+//!
+//! Each analyzer of a [`Pipeline`] reports the histogram of votes it finds
+//! the consensus in. Over time, the histograms show competing frequencies,
+//! harmonics and how sure the consensus is.
+//!
+//! [`Pipeline`]: crate::pipeline::Pipeline
+
+use crate::prelude::*;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Latest histogram of each window size, shared between the analyzers which
+/// update it, see [`Pipeline::with_spectrogram`], and the UI which shows it.
+///
+/// [`Pipeline::with_spectrogram`]:
+/// crate::pipeline::Pipeline::with_spectrogram
+#[derive(Default)]
+pub struct Spectrogram {
+    inner: Mutex<BTreeMap<usize, Histogram>>,
+}
+
+/// How many oscillators voted for each frequency bin.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    /// Timestamp of the frame which the histogram was made at.
+    pub timestamp: Duration,
+    /// Width of each bin in Hz.
+    pub bin_hz: f32,
+    /// Votes for each bin, starting from 0 Hz.
+    pub counts: Vec<usize>,
+}
+
+impl Spectrogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the histogram of given window size.
+    pub(crate) fn update(&self, window: usize, histogram: Histogram) {
+        self.inner.lock().unwrap().insert(window, histogram);
+    }
+
+    /// Latest histogram of each window size, from the smallest window.
+    pub fn latest(&self) -> BTreeMap<usize, Histogram> {
+        self.inner.lock().unwrap().clone()
+    }
+}
+
+impl Histogram {
+    /// Share of the votes in each of evenly sized rows between the lowest and
+    /// the highest frequency of interest, from the lowest.
+    pub fn rows(&self, rows_count: usize) -> Vec<f32> {
+        let total: usize = self.counts.iter().sum();
+        let range =
+            HIGHEST_FREQUENCY_OF_INTEREST - LOWEST_FREQUENCY_OF_INTEREST;
+        let row_hz = range / rows_count as f32;
+
+        (0..rows_count)
+            .map(|row| {
+                // the bin which the middle of the row falls into
+                let hz =
+                    LOWEST_FREQUENCY_OF_INTEREST + (row as f32 + 0.5) * row_hz;
+                let bin = (hz / self.bin_hz) as usize;
+                match self.counts.get(bin) {
+                    Some(count) if total > 0 => *count as f32 / total as f32,
+                    _ => 0.0,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Draws the columns of rows made by [`Histogram::rows`] as a heatmap, the
+/// more votes the darker. Missing columns are left blank. Returns RGBA pixels
+/// row by row from the highest frequency.
+pub fn paint(columns: &[Option<&[f32]>], rows_count: usize) -> Vec<u8> {
+    let mut rgba = Vec::with_capacity(columns.len() * rows_count * 4);
+    for row in (0..rows_count).rev() {
+        for column in columns {
+            let share = column.and_then(|rows| rows.get(row)).copied();
+            // a small share should still be visible
+            let intensity = share.unwrap_or_default().sqrt();
+            let shade = |dark: f32| {
+                ((1.0 - intensity * (1.0 - dark)) * 255.0).round() as u8
+            };
+
+            rgba.extend_from_slice(&[
+                shade(0.15),
+                shade(0.3),
+                shade(0.75),
+                255,
+            ]);
+        }
+    }
+
+    rgba
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_paints_histograms() {
+        // 0.5 Hz bins, most votes between 2 and 2.5 Hz
+        let histogram = Histogram {
+            timestamp: Duration::default(),
+            bin_hz: 0.5,
+            counts: vec![0, 0, 1, 0, 3, 0, 0, 0, 0],
+        };
+        let spectrogram = Spectrogram::new();
+        spectrogram.update(120, histogram);
+        let histogram = &spectrogram.latest()[&120];
+
+        let rows = histogram.rows(4);
+        assert_eq!(rows, vec![0.25, 0.75, 0.0, 0.0]);
+
+        let rgba = paint(&[Some(&rows), None], 4);
+        assert_eq!(rgba.len(), 2 * 4 * 4);
+        // no votes in the two highest rows
+        assert_eq!(&rgba[..16], &[255; 16]);
+        // the lowest row has a quarter of the votes
+        assert_eq!(&rgba[24..28], &[147, 166, 223, 255]);
+        assert_eq!(&rgba[28..32], &[255; 4]);
+    }
+}
@@ -0,0 +1,219 @@
+//! Sends the live cadence to music apps, so that they can follow the athlete.
+//!
+//! The tempo in BPM is the consensus frequency × 60, i.e. one beat per jump.
+//!
+//! - OSC messages `/jump_rope/bpm` and `/jump_rope/hz`, each with a single
+//!   float argument, are sent over UDP every [`REPORT_FREQUENCY_AFTER_MS`].
+//! - MIDI clock is sent to a MIDI port at 24 pulses per beat. The clock starts
+//!   when cadence is detected and stops when it's lost.
+//!
+//! MIDI has no live tempo message, the set tempo meta event only exists in
+//! MIDI files, so sequencers derive the tempo from the pace of the clock.
+
+use crate::app_error::{AppError, AppResult};
+use jump_rope::frequency_tracker::FrequencyTracker;
+use jump_rope::{Error, Result, REPORT_FREQUENCY_AFTER_MS};
+use midir::{MidiOutput, MidiOutputConnection};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// MIDI clock sends this many pulses per beat.
+const MIDI_CLOCK_PULSES_PER_BEAT: f32 = 24.0;
+
+const MIDI_CLOCK: u8 = 0xF8;
+const MIDI_START: u8 = 0xFA;
+const MIDI_STOP: u8 = 0xFC;
+
+/// Spawns a thread which sends the consensus as OSC messages to given UDP
+/// address. Nothing is sent while there's no consensus.
+pub fn start_osc(
+    addr: impl ToSocketAddrs,
+    tracker: Arc<FrequencyTracker>,
+) -> Result<()> {
+    let addr = addr.to_socket_addrs()?.next().ok_or_else(|| {
+        Error::InvalidArgument("OSC address resolves to nothing".to_string())
+    })?;
+    // the socket must be of the same family as the address it sends to
+    let local: SocketAddr = if addr.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(addr)?;
+
+    thread::spawn(move || loop {
+        if let Some(hz) = tracker.calculate_latest() {
+            for message in &[
+                osc_message("/jump_rope/bpm", hz * 60.0),
+                osc_message("/jump_rope/hz", hz),
+            ] {
+                // UDP errors are mostly about nobody listening yet, which
+                // is fine
+                let _ = socket.send(message);
+            }
+        }
+
+        thread::sleep(Duration::from_millis(REPORT_FREQUENCY_AFTER_MS as u64));
+    });
+
+    Ok(())
+}
+
+/// Spawns a thread which sends MIDI clock following the consensus.
+///
+/// On Linux and macOS, a virtual port with given name is created for other
+/// apps to connect to. Elsewhere, the first existing port whose name contains
+/// given name is used.
+pub fn start_midi_clock(
+    port_name: &str,
+    tracker: Arc<FrequencyTracker>,
+) -> AppResult<()> {
+    let mut connection = connect_midi(port_name)?;
+
+    thread::spawn(move || {
+        let mut running = false;
+        let mut next_pulse_at = Instant::now();
+
+        loop {
+            let hz = tracker.calculate_latest();
+            let message = match (hz, running) {
+                (Some(_), false) => Some(MIDI_START),
+                (Some(_), true) => Some(MIDI_CLOCK),
+                (None, true) => Some(MIDI_STOP),
+                (None, false) => None,
+            };
+            running = hz.is_some();
+
+            if let Some(message) = message {
+                if let Err(e) = connection.send(&[message]) {
+                    eprintln!("Cannot send MIDI clock: {}", e);
+                }
+            }
+
+            // we schedule the pulses from the previous one rather than from
+            // now, so that the time spent sending doesn't slow the tempo
+            // down, unless we've fallen behind
+            let now = Instant::now();
+            next_pulse_at = match hz {
+                Some(hz) => {
+                    let pulse = 1.0 / (hz * MIDI_CLOCK_PULSES_PER_BEAT);
+                    (next_pulse_at + Duration::from_secs_f32(pulse)).max(now)
+                }
+                None => {
+                    now + Duration::from_millis(
+                        REPORT_FREQUENCY_AFTER_MS as u64,
+                    )
+                }
+            };
+            thread::sleep(
+                next_pulse_at.saturating_duration_since(Instant::now()),
+            );
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn connect_midi(port_name: &str) -> AppResult<MidiOutputConnection> {
+    use midir::os::unix::VirtualOutput;
+
+    MidiOutput::new("jump-rope")?
+        .create_virtual(port_name)
+        .map_err(|e| AppError::Midi(e.to_string()))
+}
+
+#[cfg(not(unix))]
+fn connect_midi(port_name: &str) -> AppResult<MidiOutputConnection> {
+    let output = MidiOutput::new("jump-rope")?;
+    let port = output
+        .ports()
+        .into_iter()
+        .find(|port| {
+            output
+                .port_name(port)
+                .map(|name| name.contains(port_name))
+                .unwrap_or(false)
+        })
+        .ok_or_else(|| {
+            Error::InvalidArgument(format!("No MIDI port named {}", port_name))
+        })?;
+
+    output
+        .connect(&port, "jump-rope clock")
+        .map_err(|e| AppError::Midi(e.to_string()))
+}
+
+/// Encodes an OSC message with a single float argument.
+///
+/// http://opensoundcontrol.org/spec-1_0
+fn osc_message(address: &str, value: f32) -> Vec<u8> {
+    let mut message = vec![];
+    push_osc_string(&mut message, address);
+    push_osc_string(&mut message, ",f");
+    message.extend_from_slice(&value.to_be_bytes());
+
+    message
+}
+
+// OSC strings are null terminated and padded with nulls to a multiple of four
+// bytes.
+fn push_osc_string(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(s.as_bytes());
+    let padding = 4 - s.len() % 4;
+    buf.resize(buf.len() + padding, 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jump_rope::Report;
+
+    #[test]
+    fn it_encodes_osc_message() {
+        assert_eq!(
+            osc_message("/jump_rope/bpm", 150.0),
+            [
+                b"/jump_rope/bpm\0\0".as_ref(),
+                b",f\0\0",
+                &150.0f32.to_be_bytes(),
+            ]
+            .concat()
+        );
+        // the terminating null needs a whole padding of its own
+        assert_eq!(&osc_message("/bpm", 1.0)[..8], b"/bpm\0\0\0\0");
+    }
+
+    #[test]
+    fn it_sends_osc_over_udp() {
+        // the consensus needs at least two windows to agree
+        let tracker = Arc::new(FrequencyTracker::new(30));
+        for window in &[120, 240] {
+            tracker.update(Report {
+                window: *window,
+                frame_index: 240,
+                timestamp: Duration::from_secs(8),
+                frequency: 2.5,
+                confidence: 1.0,
+            });
+        }
+
+        let mut receivers = vec![UdpSocket::bind("127.0.0.1:0").unwrap()];
+        // not every machine has IPv6
+        receivers.extend(UdpSocket::bind("[::1]:0").ok());
+        for receiver in receivers {
+            receiver
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let addr = receiver.local_addr().unwrap();
+            start_osc(addr, Arc::clone(&tracker)).unwrap();
+
+            let mut buf = [0; 64];
+            let len = receiver.recv(&mut buf).unwrap();
+            assert_eq!(&buf[..len], osc_message("/jump_rope/bpm", 150.0));
+        }
+    }
+}
@@ -0,0 +1,277 @@
+//! Recording of what the analysis reported during a session, and replaying it
+//! later without any video.
+//!
+//! A trace is a JSON Lines file. The first line is always the
+//! [`TraceEvent::Start`] event, the rest are reports and consensus values in
+//! the order they happened.
+
+use crate::app_error::AppResult;
+use jump_rope::activity::{ActivityChange, ActivityEvent};
+use jump_rope::frequency_tracker::{FrequencyTracker, SourceStatus};
+use jump_rope::Report;
+use jump_rope::{Error, Result, REPORT_FREQUENCY_AFTER_MS};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TraceEvent {
+    Start {
+        frame_rate: usize,
+    },
+    /// See [`Report`].
+    Report {
+        seconds: f64,
+        window: usize,
+        frame_index: usize,
+        frequency: f32,
+        confidence: f32,
+        // see [`FrequencyTracker::update_from`]
+        source: usize,
+    },
+    /// What [`FrequencyTracker::calculate_latest`] returned after the reports
+    /// until this point were applied.
+    Consensus {
+        seconds: f64,
+        frequency: Option<f32>,
+    },
+    /// See [`ActivityEvent`].
+    Activity {
+        seconds: f64,
+        change: ActivityChange,
+    },
+}
+
+/// Consumes events of a live session as they happen.
+pub trait TraceSink: Send {
+    fn write(&mut self, event: &TraceEvent) -> AppResult<()>;
+}
+
+/// Writes trace events to a file as they happen.
+pub struct TraceWriter {
+    out: BufWriter<File>,
+}
+
+impl TraceWriter {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self {
+            out: BufWriter::new(File::create(path)?),
+        })
+    }
+}
+
+impl TraceSink for TraceWriter {
+    fn write(&mut self, event: &TraceEvent) -> AppResult<()> {
+        serde_json::to_writer(&mut self.out, event)?;
+        self.out.write_all(b"\n")?;
+
+        // the process exits when the window is closed without giving us a
+        // chance to flush, therefore we flush with each consensus
+        if let TraceEvent::Consensus { .. } = event {
+            self.out.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads all events of a trace.
+pub fn read(path: impl AsRef<Path>) -> Result<Vec<TraceEvent>> {
+    let file = BufReader::new(File::open(path)?);
+
+    file.lines()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|(index, line)| {
+            serde_json::from_str(&line?).map_err(|e| Error::MalformedLine {
+                line: index + 1,
+                message: e.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Spawns a thread which feeds the recorded reports into a fresh tracker, at
+/// the pace they were recorded (sped up by given factor). This is a stand-in
+/// for the live analysis, so that the UI and the consensus can be debugged
+/// without a camera.
+///
+/// When a replayed consensus differs from the recorded one, it's printed.
+/// Fails unless the speed is a positive number.
+pub fn replay(
+    events: Vec<TraceEvent>,
+    speed: f32,
+) -> Result<Arc<FrequencyTracker>> {
+    if !(speed.is_finite() && speed > 0.0) {
+        return Err(Error::InvalidArgument(format!(
+            "Cannot replay at speed {}",
+            speed
+        )));
+    }
+    let frame_rate = match events.first() {
+        Some(TraceEvent::Start { frame_rate }) => *frame_rate,
+        _ => {
+            return Err(Error::MalformedFile(
+                "Trace must begin with the start event".to_string(),
+            ))
+        }
+    };
+    let tracker = Arc::new(FrequencyTracker::new(frame_rate));
+
+    let replayed_tracker = Arc::clone(&tracker);
+    thread::spawn(move || {
+        let started_at = Instant::now();
+        // the activity is ticked regularly while waiting for the next event,
+        // because there are no events when the athlete stops jumping
+        let tick_every =
+            Duration::from_millis(REPORT_FREQUENCY_AFTER_MS as u64);
+        let tick = || {
+            let now = started_at.elapsed().mul_f32(speed);
+            if let Some(event) = replayed_tracker.tick(now) {
+                println!(
+                    "Activity {} at {:.2}s",
+                    event.change.as_str(),
+                    event.at.as_secs_f32()
+                );
+            }
+        };
+
+        for event in events {
+            if let Some(seconds) = event.seconds() {
+                let due = started_at
+                    + Duration::from_secs_f64(seconds / speed as f64);
+                while let Some(wait) =
+                    due.checked_duration_since(Instant::now())
+                {
+                    thread::sleep(wait.min(tick_every.div_f32(speed)));
+                    tick();
+                }
+            }
+
+            match event {
+                TraceEvent::Start { .. } | TraceEvent::Activity { .. } => (),
+                TraceEvent::Report { source, .. } => {
+                    let report = event.to_report().unwrap();
+                    replayed_tracker.update_from(source, report);
+                }
+                TraceEvent::Consensus { seconds, frequency } => {
+                    let replayed = replayed_tracker.calculate_latest();
+                    if replayed != frequency {
+                        println!(
+                            "Consensus at {:.2}s was {:?}, replayed {:?}",
+                            seconds, frequency, replayed
+                        );
+                    }
+                }
+            }
+        }
+
+        replayed_tracker.set_status(SourceStatus::Ended);
+        println!("Replay finished");
+    });
+
+    Ok(tracker)
+}
+
+impl TraceEvent {
+    /// The report of an analyzer of given source.
+    pub fn report(source: usize, report: &Report) -> Self {
+        Self::Report {
+            seconds: report.timestamp.as_secs_f64(),
+            window: report.window,
+            frame_index: report.frame_index,
+            frequency: report.frequency,
+            confidence: report.confidence,
+            source,
+        }
+    }
+
+    pub fn consensus(timestamp: Duration, frequency: Option<f32>) -> Self {
+        Self::Consensus {
+            seconds: timestamp.as_secs_f64(),
+            frequency,
+        }
+    }
+
+    /// Time since the start of the video, if the event is tied to any.
+    pub fn seconds(&self) -> Option<f64> {
+        match self {
+            Self::Start { .. } => None,
+            Self::Report { seconds, .. }
+            | Self::Consensus { seconds, .. }
+            | Self::Activity { seconds, .. } => Some(*seconds),
+        }
+    }
+
+    pub fn to_report(&self) -> Option<Report> {
+        match self {
+            Self::Report {
+                seconds,
+                window,
+                frame_index,
+                frequency,
+                confidence,
+                ..
+            } => Some(Report {
+                window: *window,
+                frame_index: *frame_index,
+                timestamp: Duration::from_secs_f64(*seconds),
+                frequency: *frequency,
+                confidence: *confidence,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl From<&ActivityEvent> for TraceEvent {
+    fn from(event: &ActivityEvent) -> Self {
+        Self::Activity {
+            seconds: event.at.as_secs_f64(),
+            change: event.change,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_writes_and_reads_trace() {
+        let path = std::env::temp_dir().join("jump_rope_trace_test.jsonl");
+        let report = Report {
+            window: 120,
+            frame_index: 125,
+            timestamp: Duration::from_millis(4_250),
+            frequency: 2.25,
+            confidence: 0.75,
+        };
+        let events = vec![
+            TraceEvent::Start { frame_rate: 30 },
+            TraceEvent::report(1, &report),
+            TraceEvent::consensus(Duration::from_millis(4_300), None),
+            TraceEvent::consensus(Duration::from_millis(4_500), Some(2.5)),
+        ];
+
+        let mut writer = TraceWriter::create(&path).unwrap();
+        for event in &events {
+            writer.write(event).unwrap();
+        }
+        drop(writer);
+
+        let read_events = read(&path).unwrap();
+        assert_eq!(read_events, events);
+
+        let read_report = read_events[1].to_report().unwrap();
+        assert_eq!(read_report.timestamp, report.timestamp);
+        assert_eq!(read_report.frequency, report.frequency);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,14 +1,94 @@
 //! TODO: This module is yet to be cleaned up as it's still WIP.
 
-use crate::frequency_tracker::FrequencyTracker;
-use crate::prelude::*;
-use bevy::{prelude::*, render::camera::Camera};
+use crate::glyphs;
+use crate::graph;
+use crate::workout::{self, Phase, Workout};
+use bevy::{
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    prelude::*,
+    render::{
+        camera::Camera,
+        texture::{Extent3d, TextureDimension, TextureFormat},
+    },
+};
 use bevy_prototype_lyon::{entity::ShapeBundle, prelude::*};
+use jump_rope::activity::Activity;
+use jump_rope::frequency_tracker::{FrequencyTracker, SourceStatus};
+use jump_rope::preview::{self, Preview};
+use jump_rope::spectrogram::{self, Spectrogram};
+use jump_rope::{
+    HIGHEST_FREQUENCY_OF_INTEREST, LOWEST_FREQUENCY_OF_INTEREST,
+    REPORT_FREQUENCY_AFTER_MS,
+};
+use lyon_tessellation::geom::euclid::default::Transform2D;
 use lyon_tessellation::path::Path;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 struct Tracker(Arc<FrequencyTracker>);
 
+/// Shows what the analysis sees, see [`preview`]. The panel is created with
+/// the first frame, as that's when its size is known, and again whenever the
+/// size changes.
+struct PreviewPanel {
+    preview: Arc<Preview>,
+    texture: Option<Handle<Texture>>,
+    /// The sprite which shows the texture.
+    sprite: Option<Entity>,
+    /// Version of the preview which the texture shows.
+    version: usize,
+}
+
+/// Shows how the votes spread over frequencies behind the curve, see
+/// [`spectrogram`]. The heatmap is drawn in chunks like the curve, see
+/// [`CurveChunks`].
+struct SpectrogramView {
+    spectrogram: Arc<Spectrogram>,
+    /// Rows of the histogram at each reading by window size, see
+    /// [`spectrogram::Histogram::rows`]. None if there was no histogram yet.
+    columns: BTreeMap<usize, Vec<Option<Vec<f32>>>>,
+    /// Window size which is shown, the smallest one by default.
+    window: Option<usize>,
+    /// Window size and zoom which the chunks are drawn for.
+    drawn: Option<(usize, f32)>,
+    /// Sprites of the chunks and their textures by the chunk index.
+    chunks: HashMap<usize, (Entity, Handle<Texture>)>,
+    /// Says which window size is shown.
+    label: Option<Entity>,
+}
+
+/// Thin curves of the estimate of each window size, to see which one the
+/// consensus follows, see [`FrequencyTracker::calculate_latest`]. They're
+/// drawn in chunks like the consensus curve, see [`CurveChunks`].
+struct WindowCurves {
+    /// Estimate at each reading by the window size in seconds.
+    estimates: BTreeMap<usize, Vec<Option<f32>>>,
+    /// Whether the curves are shown, the D key toggles them.
+    visible: bool,
+    /// Zoom which the chunks are drawn for.
+    drawn: Option<f32>,
+    /// Curves of each chunk by the chunk index.
+    chunks: HashMap<usize, Vec<Entity>>,
+    /// Says which color belongs to which window size.
+    legend: Vec<Entity>,
+}
+
+/// Guides the athlete through the workout, if any. The workout starts with the
+/// curve.
+struct WorkoutProgress {
+    phases: Vec<Phase>,
+    /// Consensus at each reading of [`FrequencyReadingsCounter`].
+    history: Vec<(Duration, Option<f32>)>,
+    /// Index of the phase the athlete is in.
+    current: Option<usize>,
+    finished: bool,
+    /// Lines which tell the athlete the phase and how long it lasts, in the
+    /// top left corner of the view.
+    label: Vec<Entity>,
+}
+
 /// Defines often we sample frequency readings and therefore update new target
 /// for the y coordinate of the curve.
 struct SampleNextY(Timer);
@@ -17,43 +97,204 @@ struct SampleNextY(Timer);
 /// is finished.
 struct FrequencyReadingsCounter(usize);
 
-/// The shape of the curve can be queried with this tag.
-struct FrequencyCurve;
+/// The curve is drawn in chunks of [`READINGS_PER_CHUNK`] readings. The chunks
+/// behind the tip are tessellated once and kept while around the view, these
+/// are their entities by the chunk index.
+struct CurveChunks(HashMap<usize, Entity>);
+
+/// The chunk with the tip of the curve can be queried with this tag. It's
+/// rebuilt every frame to animate the latest reading.
+struct CurveTip;
 
-/// Everytime [`SampleNextY`] finishes, we rebuild the whole curve. We therefore
-/// need to keep track of the few dozens latests constituent shapes which
-/// create the output curve.
+/// Everytime [`SampleNextY`] finishes, we add a new bit to the curve. We keep
+/// all its constituent shapes so that the athlete can scroll back, see
+/// [`Timeline`].
 struct FrequencyCurveHistory(Vec<PathCommand>);
 
-/// The frequency curve is redrawn every now and then. We keep the tip hidden
-/// under a white plane and with each tick slightly move the plane. This creates
-/// the illusion that the curve is drawn continuously.
-struct ShadePlane;
+/// How the timeline is viewed, see [`control_timeline`].
+struct Timeline {
+    /// Horizontal zoom, at 1.0 each reading is [`SINGLE_READING_TO_PX`] long.
+    zoom: f32,
+    /// Whether the camera follows the tip of the curve. Scrolling pauses it.
+    following: bool,
+}
+
+/// Target bands of the workout can be queried with this tag, as they are
+/// redrawn on zoom.
+struct WorkoutTarget;
+
+/// Axis labels and the readout move along with the camera so that they stay
+/// in view, also when the window is resized.
+enum FixedToView {
+    /// Offset on the x axis from the left edge of the view.
+    Left(f32),
+    /// Offset on the x axis from the right edge of the view.
+    Right(f32),
+}
+
+/// The live cadence readout can be queried with this tag. It holds the text
+/// it shows.
+struct Readout(String);
+
+/// The status of the source under the readout, see [`update_status`]. It
+/// holds the text it shows.
+struct StatusLabel(String);
+
+/// The ticks of the time axis around the view are drawn as the camera slides,
+/// see [`draw_time_axis`]. These are the entities of each tick, its label and
+/// grid line by the index of the tick.
+struct TimeAxis(HashMap<usize, Vec<Entity>>);
 
 /// Defines how long each new bit of the curve is.
 const SINGLE_READING_TO_PX: f32 = 20.0;
 
+/// How many readings each chunk of the curve has, see [`CurveChunks`].
+const READINGS_PER_CHUNK: usize = 32;
+
+/// How many Hz apart the ticks on the y axis are.
+const HZ_PER_TICK: f32 = 0.5;
+
+/// How many seconds apart the ticks on the time axis are.
+const SECONDS_PER_TICK: usize = 5;
+
+/// Height of the axis labels in px.
+const LABEL_HEIGHT: f32 = 14.0;
+
+/// Height of the readout in px. It must be readable from across a gym.
+const READOUT_HEIGHT: f32 = 80.0;
+
+/// Height of the status of the source in px.
+const STATUS_HEIGHT: f32 = 30.0;
+
+/// Height of the workout phase and the time left in px.
+const WORKOUT_HEIGHT: f32 = 30.0;
+
+/// Width of the panel with the y axis labels on the left of the view.
+const Y_AXIS_WIDTH: f32 = 230.0;
+
+const GRID_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+
+const LABEL_COLOR: Color = Color::rgb(0.3, 0.3, 0.3);
+
+const STATUS_COLOR: Color = Color::rgb(0.8, 0.1, 0.1);
+
+/// Width of the preview in the right of the view, see [`PreviewPanel`].
+const PREVIEW_WIDTH: f32 = 320.0;
+
+/// How many frequency bands the spectrogram has, see [`SpectrogramView`].
+const SPECTROGRAM_ROWS: usize = 64;
+
+/// Colors of the curves of the window sizes, from the smallest window, see
+/// [`WindowCurves`].
+const WINDOW_COLORS: &[Color] = &[
+    Color::rgb(0.9, 0.4, 0.1),
+    Color::rgb(0.2, 0.6, 0.2),
+    Color::rgb(0.6, 0.2, 0.8),
+];
+
+/// Each press of a zoom key, or a line of the mouse wheel, zooms the timeline
+/// by this factor.
+const ZOOM_STEP: f32 = 1.25;
+
+const MIN_ZOOM: f32 = 0.25;
+
+const MAX_ZOOM: f32 = 4.0;
+
+/// How fast the arrow keys scroll the timeline.
+const SCROLL_PX_PER_SECOND: f32 = 800.0;
+
+/// How far a line of the mouse wheel scrolls the timeline.
+const SCROLL_PX_PER_LINE: f32 = 40.0;
+
 #[derive(Debug)]
 enum PathCommand {
     MoveTo(Vec2),
     QuadraticBezier(Vec2, Vec2),
 }
 
-pub fn start(tracker: Arc<FrequencyTracker>) {
-    App::build()
-        .insert_resource(Msaa { samples: 8 })
+pub fn start(
+    tracker: Arc<FrequencyTracker>,
+    workout: Option<Workout>,
+    preview: Option<Arc<Preview>>,
+    spectrogram: Option<Arc<Spectrogram>>,
+    show_windows: bool,
+) {
+    let mut app = App::build();
+    app.insert_resource(Msaa { samples: 8 })
         .insert_resource(ClearColor(Color::rgb(1., 1., 1.)))
+        .insert_resource(WindowDescriptor {
+            title: "Jump rope".to_string(),
+            ..Default::default()
+        })
         .insert_resource(Tracker(tracker))
         .insert_resource(FrequencyReadingsCounter(0))
+        .insert_resource(TimeAxis(HashMap::new()))
+        .insert_resource(CurveChunks(HashMap::new()))
+        .insert_resource(WindowCurves {
+            estimates: BTreeMap::new(),
+            visible: show_windows,
+            drawn: None,
+            chunks: HashMap::new(),
+            legend: vec![],
+        })
+        .insert_resource(Timeline {
+            zoom: 1.0,
+            following: true,
+        })
         .insert_resource(SampleNextY::new(
             REPORT_FREQUENCY_AFTER_MS as f32 / 1000.,
         ))
         .add_plugins(DefaultPlugins)
         .add_plugin(ShapePlugin)
         .add_startup_system(setup.system())
-        .add_system(redraw_frequency_curve.system())
-        .add_system(slide_camera_and_shade_plane.system())
-        .run();
+        .add_system(sample_frequency_curve.system().label("sample"))
+        .add_system(render_frequency_curve.system().after("sample"))
+        .add_system(animate_curve_tip.system().after("sample"))
+        .add_system(sample_window_estimates.system().label("sample_windows"))
+        .add_system(render_window_curves.system().after("sample_windows"))
+        .add_system(control_timeline.system().before("slide"))
+        .add_system(slide_camera.system().label("slide"))
+        .add_system(keep_fixed_to_view.system().after("slide"))
+        .add_system(draw_time_axis.system())
+        .add_system(update_readout.system())
+        .add_system(update_status.system())
+        .add_system(export_graph.system().after("sample"));
+
+    if let Some(workout) = workout {
+        app.insert_resource(WorkoutProgress::new(&workout))
+            .add_system(draw_workout_targets.system())
+            .add_system(guide_workout.system());
+    }
+
+    if let Some(preview) = preview {
+        app.insert_resource(PreviewPanel {
+            preview,
+            texture: None,
+            sprite: None,
+            version: 0,
+        })
+        .add_system(show_preview.system());
+    }
+
+    if let Some(spectrogram) = spectrogram {
+        app.insert_resource(SpectrogramView {
+            spectrogram,
+            columns: BTreeMap::new(),
+            window: None,
+            drawn: None,
+            chunks: HashMap::new(),
+            label: None,
+        })
+        .add_system(sample_spectrogram.system().label("sample_spectrogram"))
+        .add_system(
+            render_spectrogram
+                .system()
+                .after("sample")
+                .after("sample_spectrogram"),
+        );
+    }
+
+    app.run();
 }
 
 fn setup(mut commands: Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
@@ -63,21 +304,8 @@ fn setup(mut commands: Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
     cam.transform.translation.y = hz_to_y(HIGHEST_FREQUENCY_OF_INTEREST / 2.);
     commands.spawn_bundle(cam);
 
-    // a large white plane which obstructs tip of the curve, see the
-    // [`ShadePlane`] for more info
-    let mut plane = SpriteBundle {
-        material: materials.add(Color::rgb(1.0, 0.0, 1.0).into()),
-        sprite: Sprite::new(Vec2::new(30.0, 5_000.)),
-        ..Default::default()
-    };
-    plane.transform.translation.z = 1.0;
-    commands.spawn_bundle(plane).insert(ShadePlane);
-
     let mut history = FrequencyCurveHistory::new();
     history.move_to(Vec2::new(0.0, 0.0));
-    commands
-        .spawn_bundle(history.shape())
-        .insert(FrequencyCurve);
     commands.insert_resource(history);
 
     // draws two lines which are the min and max limit for any observed
@@ -92,23 +320,461 @@ fn setup(mut commands: Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
             Transform::default(),
         ));
     });
+
+    // the y axis labels are on a white panel on the left of the view, so that
+    // the curve slides under them
+    let mut panel = SpriteBundle {
+        material: materials.add(Color::WHITE.into()),
+        sprite: Sprite::new(Vec2::new(Y_AXIS_WIDTH, 5_000.)),
+        ..Default::default()
+    };
+    panel.transform.translation.z = 2.0;
+    commands
+        .spawn_bundle(panel)
+        .insert(FixedToView::Left(Y_AXIS_WIDTH / 2.0));
+
+    let ticks_count = (HIGHEST_FREQUENCY_OF_INTEREST / HZ_PER_TICK) as usize;
+    for tick in 1..=ticks_count {
+        let hz = tick as f32 * HZ_PER_TICK;
+        let y = hz_to_y(hz);
+        commands.spawn_bundle(GeometryBuilder::build_as(
+            &shapes::Line(Vec2::new(0., y), Vec2::new(1_000_000., y)),
+            ShapeColors::new(GRID_COLOR),
+            DrawMode::Stroke(StrokeOptions::default().with_line_width(1.0)),
+            Transform::from_xyz(0.0, 0.0, -2.0),
+        ));
+
+        // e.g. "2.5 Hz 150 BPM"
+        let label = format!("{:.1} Hz {:>3.0} BPM", hz, hz * 60.0);
+        commands
+            .spawn_bundle(text_shape(
+                &label,
+                LABEL_HEIGHT,
+                LABEL_COLOR,
+                // placed by [`keep_fixed_to_view`]
+                Transform::from_xyz(0.0, y - LABEL_HEIGHT / 2.0, 3.0),
+            ))
+            .insert(FixedToView::Left(10.0));
+    }
 }
 
-fn slide_camera_and_shade_plane(
-    time: Res<Time>,
-    readings_counter: ResMut<FrequencyReadingsCounter>,
+// Moves the axis labels and the readout along with the camera.
+fn keep_fixed_to_view(
+    windows: Res<Windows>,
     mut query: QuerySet<(
-        Query<&mut Transform, With<Camera>>,
-        Query<&mut Transform, With<ShadePlane>>,
+        Query<&Transform, With<Camera>>,
+        Query<(&mut Transform, &FixedToView)>,
     )>,
+) {
+    let cam_x = query
+        .q0()
+        .single()
+        .expect("Cannot get camera")
+        .translation
+        .x;
+    let view_width = view_width(&windows);
+
+    for (mut transform, fixed) in query.q1_mut().iter_mut() {
+        transform.translation.x = fixed.x(cam_x, view_width);
+    }
+}
+
+// Draws the ticks and labels of the time axis around the view, and removes
+// those which slid out of it. They are all drawn again when the timeline is
+// zoomed.
+fn draw_time_axis(
+    mut commands: Commands,
+    mut axis: ResMut<TimeAxis>,
+    timeline: Res<Timeline>,
+    windows: Res<Windows>,
+    camera: Query<&Transform, With<Camera>>,
+) {
+    if timeline.is_changed() {
+        for (_, entities) in axis.0.drain() {
+            for entity in entities {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+
+    // with a view width to each side, like the chunks of the curve
+    let cam_x = camera.single().expect("Cannot get camera").translation.x;
+    let margin = view_width(&windows);
+    let tick_px = duration_to_x(Duration::from_secs(SECONDS_PER_TICK as u64))
+        * timeline.zoom;
+    let tick_at = |x: f32| (x / tick_px).max(0.0) as usize;
+    let ticks = tick_at(cam_x - margin)..=tick_at(cam_x + margin);
+
+    axis.0.retain(|tick, entities| {
+        if ticks.contains(tick) {
+            return true;
+        }
+        for entity in entities.drain(..) {
+            commands.entity(entity).despawn();
+        }
+        false
+    });
+
+    let y = hz_to_y(-0.2);
+    for tick in ticks {
+        if axis.0.contains_key(&tick) {
+            continue;
+        }
+
+        let x = tick as f32 * tick_px;
+        let mut entities = vec![];
+        entities.push(
+            commands
+                .spawn_bundle(GeometryBuilder::build_as(
+                    &shapes::Line(Vec2::new(x, y), Vec2::new(x, y - 10.0)),
+                    ShapeColors::new(Color::GRAY),
+                    DrawMode::Stroke(
+                        StrokeOptions::default().with_line_width(3.0),
+                    ),
+                    Transform::default(),
+                ))
+                .id(),
+        );
+        entities.push(
+            commands
+                .spawn_bundle(GeometryBuilder::build_as(
+                    &shapes::Line(
+                        Vec2::new(x, y),
+                        Vec2::new(x, hz_to_y(HIGHEST_FREQUENCY_OF_INTEREST)),
+                    ),
+                    ShapeColors::new(GRID_COLOR),
+                    DrawMode::Stroke(
+                        StrokeOptions::default().with_line_width(1.0),
+                    ),
+                    Transform::from_xyz(0.0, 0.0, -2.0),
+                ))
+                .id(),
+        );
+
+        let label = format!("{}s", tick * SECONDS_PER_TICK);
+        let label_x = x - glyphs::width(&label, LABEL_HEIGHT) / 2.0;
+        entities.push(
+            commands
+                .spawn_bundle(text_shape(
+                    &label,
+                    LABEL_HEIGHT,
+                    LABEL_COLOR,
+                    Transform::from_xyz(label_x, y - 20.0 - LABEL_HEIGHT, 0.0),
+                ))
+                .id(),
+        );
+
+        axis.0.insert(tick, entities);
+    }
+}
+
+// Shows the current cadence in large digits in the top right corner. The
+// readout is only rebuilt when its text changes.
+fn update_readout(
+    mut commands: Commands,
+    tracker: Res<Tracker>,
+    windows: Res<Windows>,
+    camera: Query<&Transform, With<Camera>>,
+    readout: Query<(Entity, &Readout)>,
+) {
+    let text = match (tracker.0.activity(), tracker.0.calculate_latest()) {
+        (Activity::Jumping, Some(hz)) => format!("{:.0} BPM", hz * 60.0),
+        _ => "--- BPM".to_string(),
+    };
+
+    if let Ok((entity, shown)) = readout.single() {
+        if shown.0 == text {
+            return;
+        }
+        commands.entity(entity).despawn();
+    }
+
+    let cam_x = camera.single().expect("Cannot get camera").translation.x;
+    // aligned to the right edge of the view
+    let fixed =
+        FixedToView::Right(-40.0 - glyphs::width(&text, READOUT_HEIGHT));
+    let x = fixed.x(cam_x, view_width(&windows));
+    let y = hz_to_y(HIGHEST_FREQUENCY_OF_INTEREST) + 40.0;
+    commands
+        .spawn_bundle(text_shape(
+            &text,
+            READOUT_HEIGHT,
+            Color::BLACK,
+            Transform::from_xyz(x, y, 3.0),
+        ))
+        .insert(fixed)
+        .insert(Readout(text));
+}
+
+// Shows under the readout why the cadence doesn't update, e.g. the camera is
+// being reconnected. Nothing is shown while the source is running.
+fn update_status(
+    mut commands: Commands,
+    tracker: Res<Tracker>,
+    windows: Res<Windows>,
+    camera: Query<&Transform, With<Camera>>,
+    label: Query<(Entity, &StatusLabel)>,
+) {
+    let text = match tracker.0.status() {
+        SourceStatus::Running => "",
+        SourceStatus::Reconnecting(_) => "reconnecting",
+        SourceStatus::Ended => "ended",
+        SourceStatus::Failed(_) => "failed",
+    };
+
+    if let Ok((entity, shown)) = label.single() {
+        if shown.0 == text {
+            return;
+        }
+        commands.entity(entity).despawn();
+    }
+    if text.is_empty() {
+        return;
+    }
+
+    let cam_x = camera.single().expect("Cannot get camera").translation.x;
+    // aligned to the right edge of the view, like the readout
+    let fixed = FixedToView::Right(-40.0 - glyphs::width(text, STATUS_HEIGHT));
+    let x = fixed.x(cam_x, view_width(&windows));
+    let y =
+        hz_to_y(HIGHEST_FREQUENCY_OF_INTEREST) + 40.0 - 15.0 - STATUS_HEIGHT;
+    commands
+        .spawn_bundle(text_shape(
+            text,
+            STATUS_HEIGHT,
+            STATUS_COLOR,
+            Transform::from_xyz(x, y, 3.0),
+        ))
+        .insert(fixed)
+        .insert(StatusLabel(text.to_string()));
+}
+
+// Draws the target band of each interval where the curve will be at that time.
+// They are drawn again when the timeline is zoomed.
+fn draw_workout_targets(
+    mut commands: Commands,
+    progress: Res<WorkoutProgress>,
+    timeline: Res<Timeline>,
+    existing_targets: Query<Entity, With<WorkoutTarget>>,
+) {
+    if !timeline.is_changed() {
+        return;
+    }
+    for entity in existing_targets.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    for phase in &progress.phases {
+        let target = match phase.target {
+            Some(target) => target,
+            None => continue,
+        };
+
+        let x1 = duration_to_x(phase.starts_at) * timeline.zoom;
+        let x2 = duration_to_x(phase.ends_at()) * timeline.zoom;
+        let y1 = hz_to_y(target.hz - target.tolerance_hz);
+        let y2 = hz_to_y(target.hz + target.tolerance_hz);
+        let band = shapes::Polygon {
+            points: vec![
+                Vec2::new(x1, y1),
+                Vec2::new(x2, y1),
+                Vec2::new(x2, y2),
+                Vec2::new(x1, y2),
+            ],
+            closed: true,
+        };
+
+        commands
+            .spawn_bundle(GeometryBuilder::build_as(
+                &band,
+                ShapeColors::new(Color::rgb(0.8, 0.95, 0.8)),
+                DrawMode::Fill(FillOptions::default()),
+                // behind the curve
+                Transform::from_xyz(0.0, 0.0, -1.0),
+            ))
+            .insert(WorkoutTarget);
+    }
+}
+
+// With each reading, records the consensus and shows the athlete which phase
+// they are in and how long it lasts in the view and the window title. When
+// the workout is over, scores how well the athlete kept the targets.
+fn guide_workout(
+    mut commands: Commands,
+    tracker: Res<Tracker>,
+    readings_counter: Res<FrequencyReadingsCounter>,
+    mut progress: ResMut<WorkoutProgress>,
+    mut windows: ResMut<Windows>,
+    camera: Query<&Transform, With<Camera>>,
+) {
+    if progress.finished || progress.history.len() > readings_counter.0 {
+        return;
+    }
+
+    let elapsed = readings_count_to_duration(readings_counter.0);
+    progress
+        .history
+        .push((elapsed, tracker.0.calculate_latest()));
+
+    let current = progress.phases.iter().position(|p| p.contains(elapsed));
+    let lines = match current {
+        Some(index) => {
+            let phase = &progress.phases[index];
+            if progress.current != current {
+                println!("{}", phase.describe());
+            }
+
+            let remaining = (phase.ends_at() - elapsed).as_secs();
+            vec![
+                phase.describe(),
+                format!("{}:{:02} left", remaining / 60, remaining % 60),
+            ]
+        }
+        None => {
+            progress.finished = true;
+            let adherence =
+                workout::adherence(&progress.phases, &progress.history);
+            for (index, score) in adherence.intervals.iter().enumerate() {
+                println!("Interval {}: {:.0}%", index + 1, score * 100.0);
+            }
+            println!(
+                "Workout finished, on target {:.0}% of the time",
+                adherence.overall * 100.0
+            );
+
+            vec![
+                "Workout finished".to_string(),
+                format!("{:.0}% on target", adherence.overall * 100.0),
+            ]
+        }
+    };
+    progress.current = current;
+
+    for entity in progress.label.drain(..) {
+        commands.entity(entity).despawn();
+    }
+    let window = match windows.get_primary_mut() {
+        Some(window) => window,
+        None => return,
+    };
+    window.set_title(lines.join(", "));
+
+    let cam_x = camera.single().expect("Cannot get camera").translation.x;
+    // right of the y axis labels, level with the readout
+    let fixed = || FixedToView::Left(Y_AXIS_WIDTH + 20.0);
+    let x = fixed().x(cam_x, window.width());
+    let top = hz_to_y(HIGHEST_FREQUENCY_OF_INTEREST) + 40.0 + READOUT_HEIGHT;
+    for (index, line) in lines.iter().enumerate() {
+        let y = top - (index + 1) as f32 * (WORKOUT_HEIGHT + 10.0);
+        let entity = commands
+            .spawn_bundle(text_shape(
+                line,
+                WORKOUT_HEIGHT,
+                Color::BLACK,
+                Transform::from_xyz(x, y, 3.0),
+            ))
+            .insert(fixed())
+            .id();
+        progress.label.push(entity);
+    }
+}
+
+// Space pauses and resumes following the curve. The arrow keys and the mouse
+// wheel scroll the timeline, plus and minus keys or the wheel with control
+// held zoom it.
+fn control_timeline(
+    time: Res<Time>,
+    keys: Res<Input<KeyCode>>,
+    mut wheel: EventReader<MouseWheel>,
+    readings_counter: Res<FrequencyReadingsCounter>,
+    mut timeline: ResMut<Timeline>,
+    mut camera: Query<&mut Transform, With<Camera>>,
+) {
+    let zooming =
+        keys.pressed(KeyCode::LControl) || keys.pressed(KeyCode::RControl);
+
+    let mut scroll = 0.0;
+    if keys.pressed(KeyCode::Left) {
+        scroll -= SCROLL_PX_PER_SECOND * time.delta_seconds();
+    }
+    if keys.pressed(KeyCode::Right) {
+        scroll += SCROLL_PX_PER_SECOND * time.delta_seconds();
+    }
+
+    let mut zoom_steps = 0.0;
+    if keys.just_pressed(KeyCode::Equals)
+        || keys.just_pressed(KeyCode::NumpadAdd)
+    {
+        zoom_steps += 1.0;
+    }
+    if keys.just_pressed(KeyCode::Minus)
+        || keys.just_pressed(KeyCode::NumpadSubtract)
+    {
+        zoom_steps -= 1.0;
+    }
+
+    for event in wheel.iter() {
+        let (x, y) = match event.unit {
+            MouseScrollUnit::Line => (event.x, event.y),
+            MouseScrollUnit::Pixel => {
+                (event.x / SCROLL_PX_PER_LINE, event.y / SCROLL_PX_PER_LINE)
+            }
+        };
+
+        if zooming {
+            zoom_steps += y;
+        } else {
+            // wheel up goes back in time
+            scroll += (x - y) * SCROLL_PX_PER_LINE;
+        }
+    }
+
+    if keys.just_pressed(KeyCode::Space) {
+        timeline.following = !timeline.following;
+    }
+
+    if scroll != 0.0 {
+        if timeline.following {
+            timeline.following = false;
+        }
+
+        // cannot scroll past the tip of the curve
+        let tip_x = frequency_readings_count_to_x(readings_counter.as_usize())
+            * timeline.zoom;
+        let cam =
+            &mut camera.single_mut().expect("Cannot get camera").translation;
+        cam.x = (cam.x + scroll).min(tip_x).max(0.0);
+    }
+
+    if zoom_steps != 0.0 {
+        let zoom = (timeline.zoom * ZOOM_STEP.powf(zoom_steps))
+            .clamp(MIN_ZOOM, MAX_ZOOM);
+        // keeps the same time in the middle of the view
+        let ratio = zoom / timeline.zoom;
+
+        camera
+            .single_mut()
+            .expect("Cannot get camera")
+            .translation
+            .x *= ratio;
+
+        timeline.zoom = zoom;
+    }
+}
+
+fn slide_camera(
+    time: Res<Time>,
+    readings_counter: ResMut<FrequencyReadingsCounter>,
+    timeline: Res<Timeline>,
+    mut camera: Query<&mut Transform, With<Camera>>,
 ) {
     let dx = |x: f32, offset: f32| -> f32 {
         // remove 5pxs to always slide slightly behind the curve to avoid
         // exposing
         // TODO
+        let readings_count = (readings_counter.as_f32() - offset) as usize;
         let target_x =
-            frequency_readings_count_to_x((readings_counter.as_f32() - offset) as usize)
-                - 5.0;
+            frequency_readings_count_to_x(readings_count) * timeline.zoom - 5.0;
         let nudge_by = (target_x - x).max(0.);
 
         nudge_by
@@ -116,44 +782,25 @@ fn slide_camera_and_shade_plane(
                 / REPORT_FREQUENCY_AFTER_MS as f32)
     };
 
-    if readings_counter.as_usize() > 6 {
+    if timeline.following && readings_counter.as_usize() > 6 {
         // start updating the camera only after a few updates
-        let cam = &mut query
-            .q0_mut()
-            .single_mut()
-            .expect("Cannot get camera")
-            .translation;
+        let cam =
+            &mut camera.single_mut().expect("Cannot get camera").translation;
         cam.x += dx(cam.x, 6.0);
     }
-
-    let plane = &mut query
-        .q1_mut()
-        .single_mut()
-        .expect("Cannot get shade plane")
-        .translation;
-    plane.x += dx(plane.x, -1.5);
 }
 
-fn redraw_frequency_curve(
-    mut cmd: Commands,
+fn sample_frequency_curve(
     time: Res<Time>,
     mut timer: ResMut<SampleNextY>,
     tracker: Res<Tracker>,
     mut readings_counter: ResMut<FrequencyReadingsCounter>,
     mut history: ResMut<FrequencyCurveHistory>,
-    existing_curve: Query<Entity, With<FrequencyCurve>>,
 ) {
     if !timer.0.tick(time.delta()).just_finished() {
         return;
     }
 
-    // remove currently drawn path because we will rerender
-    if let Ok(entity) = existing_curve.single() {
-        cmd.entity(entity)
-            .remove_bundle::<ShapeBundle>()
-            .remove::<FrequencyCurve>();
-    }
-
     let (x1, y1) = {
         let c = history.current_position();
         (c.x, c.y)
@@ -161,12 +808,12 @@ fn redraw_frequency_curve(
 
     // get latest frequency reading and calculate new y coord based on
     // that, and since x is time we just increment
-    let y2 = if let Some(hz) = tracker.0.calculate_latest() {
-        hz_to_y(hz)
-    } else {
-        // slowly go down with frequency since there isn't any useful
-        // reading
-        y1 * 0.9
+    let y2 = match (tracker.0.activity(), tracker.0.calculate_latest()) {
+        (Activity::Jumping, Some(hz)) => hz_to_y(hz),
+        // the athlete might continue after a trip, so we keep the curve
+        (Activity::Tripped, _) => y1,
+        // slowly go down with frequency since the athlete isn't jumping
+        _ => y1 * 0.9,
     };
     let x2 = frequency_readings_count_to_x(readings_counter.as_usize() + 1);
     // this is where our new curve must end up
@@ -215,22 +862,648 @@ fn redraw_frequency_curve(
     history.quadratic_bezier_to(curvature, dest);
     history.move_to(dest);
 
-    // re-insert new curve mesh
-    cmd.spawn_bundle(history.shape()).insert(FrequencyCurve);
-
     // next iteration will consider next point on x axis
     readings_counter.0 += 1;
 }
 
+// Draws the chunks of the curve behind the tip around the view which aren't
+// drawn yet, and removes those far out of the view. All chunks are drawn
+// again when the timeline is zoomed.
+//
+// This way only the tip is tessellated as the session goes, and only the
+// chunks around the view are kept, no matter how long the curve is, see
+// [`animate_curve_tip`].
+fn render_frequency_curve(
+    mut cmd: Commands,
+    readings_counter: Res<FrequencyReadingsCounter>,
+    history: Res<FrequencyCurveHistory>,
+    timeline: Res<Timeline>,
+    windows: Res<Windows>,
+    mut chunks: ResMut<CurveChunks>,
+    camera: Query<&Transform, With<Camera>>,
+) {
+    if timeline.is_changed() {
+        for (_, entity) in chunks.0.drain() {
+            cmd.entity(entity).despawn();
+        }
+    }
+
+    let tip_chunk = tip_chunk(readings_counter.as_usize());
+
+    // in x at zoom 1, with a view width to each side so that the chunks are
+    // ready before scrolled into the view
+    let cam_x = camera.single().expect("Cannot get camera").translation.x
+        / timeline.zoom;
+    let margin = view_width(&windows) / timeline.zoom;
+    let chunk_at = |x: f32| {
+        (x / SINGLE_READING_TO_PX).max(0.0) as usize / READINGS_PER_CHUNK
+    };
+
+    let last_chunk = (chunk_at(cam_x + margin) + 1).min(tip_chunk);
+    let around_view = chunk_at(cam_x - margin)..last_chunk;
+
+    // the chunks which slid out are drawn again if scrolled back to
+    chunks.0.retain(|chunk, entity| {
+        if around_view.contains(chunk) {
+            return true;
+        }
+        cmd.entity(*entity).despawn();
+        false
+    });
+
+    for chunk in around_view {
+        if chunks.0.contains_key(&chunk) {
+            continue;
+        }
+
+        let readings =
+            chunk * READINGS_PER_CHUNK..(chunk + 1) * READINGS_PER_CHUNK;
+        let entity = cmd
+            .spawn_bundle(history.shape(readings, timeline.zoom, 1.0))
+            .id();
+        chunks.0.insert(chunk, entity);
+    }
+}
+
+// Rebuilds the chunk with the tip of the curve every frame. The latest reading
+// is drawn bit by bit until the next one, so the curve seems to be drawn
+// continuously.
+fn animate_curve_tip(
+    mut cmd: Commands,
+    timer: Res<SampleNextY>,
+    readings_counter: Res<FrequencyReadingsCounter>,
+    history: Res<FrequencyCurveHistory>,
+    timeline: Res<Timeline>,
+    tip: Query<Entity, With<CurveTip>>,
+) {
+    if let Ok(entity) = tip.single() {
+        cmd.entity(entity).despawn();
+    }
+
+    let readings_count = readings_counter.as_usize();
+    let readings =
+        tip_chunk(readings_count) * READINGS_PER_CHUNK..readings_count;
+    cmd.spawn_bundle(history.shape(readings, timeline.zoom, timer.0.percent()))
+        .insert(CurveTip);
+}
+
+// Keeps the estimate of each window size with each reading of the curve, and
+// toggles their curves with the D key.
+fn sample_window_estimates(
+    keys: Res<Input<KeyCode>>,
+    tracker: Res<Tracker>,
+    readings_counter: Res<FrequencyReadingsCounter>,
+    mut curves: ResMut<WindowCurves>,
+) {
+    if keys.just_pressed(KeyCode::D) {
+        curves.visible = !curves.visible;
+    }
+
+    let readings_count = readings_counter.as_usize();
+    if !readings_counter.is_changed() || readings_count == 0 {
+        return;
+    }
+
+    // the estimate is at the end of the bit of the curve which the reading
+    // added, same as the consensus
+    let reading = readings_count - 1;
+    let windows = tracker.0.snapshot().windows;
+    // the windows of other sources are the same sizes, only the first one's
+    // are plotted
+    for estimate in windows.into_iter().filter(|e| e.source == 0) {
+        let window = estimate.window_seconds.round() as usize;
+        let estimates = curves.estimates.entry(window).or_default();
+        if estimates.len() <= reading {
+            estimates.resize(reading + 1, None);
+        }
+        estimates[reading] = Some(estimate.frequency);
+    }
+}
+
+// Draws the chunks of the window curves around the view which aren't drawn
+// yet, removes those far out of the view, and redraws the chunk with the tip
+// with each reading. All chunks are drawn again when the timeline is zoomed,
+// and removed when hidden.
+fn render_window_curves(
+    mut commands: Commands,
+    readings_counter: Res<FrequencyReadingsCounter>,
+    timeline: Res<Timeline>,
+    windows: Res<Windows>,
+    mut curves: ResMut<WindowCurves>,
+    camera: Query<&Transform, With<Camera>>,
+) {
+    let zoom = Some(timeline.zoom).filter(|_| curves.visible);
+    let new_windows = curves.legend.len() != curves.estimates.len();
+    if curves.drawn != zoom || (curves.visible && new_windows) {
+        for (_, entities) in curves.chunks.drain() {
+            for entity in entities {
+                commands.entity(entity).despawn();
+            }
+        }
+        for entity in curves.legend.drain(..) {
+            commands.entity(entity).despawn();
+        }
+        curves.drawn = zoom;
+
+        if curves.visible {
+            // e.g. "4s 8s 12s" above the graph, each in its color
+            let mut offset = Y_AXIS_WIDTH + 10.0;
+            let cam_x =
+                camera.single().expect("Cannot get camera").translation.x;
+            let view_width = view_width(&windows);
+            let y = hz_to_y(HIGHEST_FREQUENCY_OF_INTEREST) + 10.0;
+            let legend: Vec<_> = curves
+                .estimates
+                .keys()
+                .zip(WINDOW_COLORS.iter().cycle())
+                .map(|(seconds, color)| {
+                    let text = format!("{}s", seconds);
+                    let fixed = FixedToView::Left(offset);
+                    let transform = Transform::from_xyz(
+                        fixed.x(cam_x, view_width),
+                        y + 2.0 * LABEL_HEIGHT,
+                        3.0,
+                    );
+                    let entity = commands
+                        .spawn_bundle(text_shape(
+                            &text,
+                            LABEL_HEIGHT,
+                            *color,
+                            transform,
+                        ))
+                        .insert(fixed)
+                        .id();
+                    offset += glyphs::width(&text, LABEL_HEIGHT) + 20.0;
+
+                    entity
+                })
+                .collect();
+            curves.legend = legend;
+        }
+    }
+
+    if !curves.visible {
+        return;
+    }
+
+    let tip_chunk = tip_chunk(readings_counter.as_usize());
+
+    // same as the chunks of the curve, see [`render_frequency_curve`]
+    let cam_x = camera.single().expect("Cannot get camera").translation.x
+        / timeline.zoom;
+    let margin = view_width(&windows) / timeline.zoom;
+    let chunk_at = |x: f32| {
+        (x / SINGLE_READING_TO_PX).max(0.0) as usize / READINGS_PER_CHUNK
+    };
+
+    let last_chunk = chunk_at(cam_x + margin).min(tip_chunk);
+    let around_view = chunk_at(cam_x - margin)..=last_chunk;
+
+    curves.chunks.retain(|chunk, entities| {
+        if around_view.contains(chunk) {
+            return true;
+        }
+        for entity in entities.drain(..) {
+            commands.entity(entity).despawn();
+        }
+        false
+    });
+
+    for chunk in around_view {
+        if curves.chunks.contains_key(&chunk) {
+            if chunk != tip_chunk || !readings_counter.is_changed() {
+                continue;
+            }
+            for entity in curves.chunks.remove(&chunk).unwrap_or_default() {
+                commands.entity(entity).despawn();
+            }
+        }
+
+        let entities = curves
+            .estimates
+            .values()
+            .zip(WINDOW_COLORS.iter().cycle())
+            .map(|(estimates, color)| {
+                let path = window_curve_path(estimates, chunk, timeline.zoom);
+                commands
+                    .spawn_bundle(GeometryBuilder::build_as(
+                        &path,
+                        ShapeColors::new(*color),
+                        DrawMode::Stroke(
+                            StrokeOptions::default().with_line_width(1.5),
+                        ),
+                        Transform::from_xyz(0.0, 0.0, -1.0),
+                    ))
+                    .id()
+            })
+            .collect();
+        curves.chunks.insert(chunk, entities);
+    }
+}
+
+// Shows the latest frame with the oscillators drawn on it whenever there's a
+// new one.
+fn show_preview(
+    mut commands: Commands,
+    mut panel: ResMut<PreviewPanel>,
+    mut textures: ResMut<Assets<Texture>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    windows: Res<Windows>,
+    camera: Query<&Transform, With<Camera>>,
+) {
+    let (version, frame, votes) = match panel.preview.latest() {
+        Some(latest) if latest.0 != panel.version => latest,
+        _ => return,
+    };
+    panel.version = version;
+    let data = preview::paint(&frame.image, &votes);
+    let (width, height) = frame.image.dimensions();
+
+    let texture = panel.texture.as_ref().and_then(|t| textures.get_mut(t));
+    if let Some(texture) = texture {
+        if texture.size == Extent3d::new(width, height, 1) {
+            texture.data = data;
+            return;
+        }
+    }
+    // e.g. the camera was reconnected at another size, the texture is freed
+    // with the sprite
+    if let Some(sprite) = panel.sprite.take() {
+        commands.entity(sprite).despawn();
+    }
+
+    let texture = textures.add(Texture::new(
+        Extent3d::new(width, height, 1),
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    ));
+
+    // in the right of the view, above the bottom line of the graph
+    let size =
+        Vec2::new(PREVIEW_WIDTH, PREVIEW_WIDTH * height as f32 / width as f32);
+    let fixed = FixedToView::Right(-20.0 - size.x / 2.0);
+    let cam_x = camera.single().expect("Cannot get camera").translation.x;
+    let x = fixed.x(cam_x, view_width(&windows));
+    let y = hz_to_y(0.0) + 20.0 + size.y / 2.0;
+    let sprite = commands
+        .spawn_bundle(SpriteBundle {
+            material: materials.add(texture.clone().into()),
+            sprite: Sprite::new(size),
+            transform: Transform::from_xyz(x, y, 4.0),
+            ..Default::default()
+        })
+        .insert(fixed)
+        .id();
+    panel.texture = Some(texture);
+    panel.sprite = Some(sprite);
+}
+
+// Saves the curve drawn so far as SVG and PNG images into the working
+// directory with the S key, see [`graph`].
+fn export_graph(
+    keys: Res<Input<KeyCode>>,
+    readings_counter: Res<FrequencyReadingsCounter>,
+    history: Res<FrequencyCurveHistory>,
+) {
+    if !keys.just_pressed(KeyCode::S) {
+        return;
+    }
+
+    // from px back to seconds and Hz, see [`hz_to_y`]
+    let px_to_seconds =
+        REPORT_FREQUENCY_AFTER_MS as f32 / 1000.0 / SINGLE_READING_TO_PX;
+    let curve = history
+        .build_path(0..readings_counter.as_usize(), 1.0, 1.0)
+        .transformed(&Transform2D::scale(px_to_seconds, hz_to_y(1.0).recip()));
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Clock is before 1970")
+        .as_secs();
+    for extension in &["svg", "png"] {
+        let path = format!("graph-{}.{}", now, extension);
+        match graph::save(&curve, &path) {
+            Ok(()) => println!("Graph saved to {}", path),
+            Err(e) => eprintln!("Cannot save graph to {}: {}", path, e),
+        }
+    }
+}
+
+// Keeps the histograms of all window sizes with each reading of the curve,
+// and switches the shown window size with the W key.
+fn sample_spectrogram(
+    keys: Res<Input<KeyCode>>,
+    readings_counter: Res<FrequencyReadingsCounter>,
+    mut view: ResMut<SpectrogramView>,
+) {
+    if keys.just_pressed(KeyCode::W) {
+        // the next larger window, or the smallest one after the largest
+        let next = match view.window {
+            Some(shown) => view.columns.range(shown + 1..).next(),
+            None => None,
+        };
+        view.window = next
+            .or_else(|| view.columns.iter().next())
+            .map(|(window, _)| *window);
+    }
+
+    let readings_count = readings_counter.as_usize();
+    if !readings_counter.is_changed() || readings_count == 0 {
+        return;
+    }
+
+    // the column is under the bit of the curve which the reading added
+    let reading = readings_count - 1;
+    for (window, histogram) in view.spectrogram.latest() {
+        let columns = view.columns.entry(window).or_default();
+        if columns.len() <= reading {
+            columns.resize(reading + 1, None);
+        }
+        columns[reading] = Some(histogram.rows(SPECTROGRAM_ROWS));
+    }
+
+    if view.window.is_none() {
+        view.window = view.columns.keys().next().copied();
+    }
+}
+
+// Draws the chunks of the spectrogram around the view which aren't drawn yet,
+// removes those far out of the view, and repaints the chunk with the tip with
+// each reading. All chunks are drawn again when the timeline is zoomed or
+// another window size is shown.
+#[allow(clippy::too_many_arguments)]
+fn render_spectrogram(
+    mut commands: Commands,
+    readings_counter: Res<FrequencyReadingsCounter>,
+    timeline: Res<Timeline>,
+    windows: Res<Windows>,
+    mut view: ResMut<SpectrogramView>,
+    mut textures: ResMut<Assets<Texture>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    camera: Query<&Transform, With<Camera>>,
+) {
+    let shown = match view.window {
+        Some(shown) => shown,
+        None => return,
+    };
+
+    if view.drawn != Some((shown, timeline.zoom)) {
+        for (_, (entity, _)) in view.chunks.drain() {
+            commands.entity(entity).despawn();
+        }
+        if let Some(label) = view.label.take() {
+            commands.entity(label).despawn();
+        }
+
+        // the window size in seconds is the inverse of the bin width
+        let bin_hz = match view.spectrogram.latest().get(&shown) {
+            Some(histogram) => histogram.bin_hz,
+            None => return,
+        };
+        let text = format!("window {:.0}s", 1.0 / bin_hz);
+        let fixed = FixedToView::Left(Y_AXIS_WIDTH + 10.0);
+        let cam_x = camera.single().expect("Cannot get camera").translation.x;
+        let x = fixed.x(cam_x, view_width(&windows));
+        let y = hz_to_y(HIGHEST_FREQUENCY_OF_INTEREST) + 10.0;
+        let label = commands
+            .spawn_bundle(text_shape(
+                &text,
+                LABEL_HEIGHT,
+                LABEL_COLOR,
+                Transform::from_xyz(x, y, 3.0),
+            ))
+            .insert(fixed)
+            .id();
+        view.label = Some(label);
+        view.drawn = Some((shown, timeline.zoom));
+    }
+
+    let tip_chunk = tip_chunk(readings_counter.as_usize());
+
+    // same as the chunks of the curve, see [`render_frequency_curve`]
+    let cam_x = camera.single().expect("Cannot get camera").translation.x
+        / timeline.zoom;
+    let margin = view_width(&windows) / timeline.zoom;
+    let chunk_at = |x: f32| {
+        (x / SINGLE_READING_TO_PX).max(0.0) as usize / READINGS_PER_CHUNK
+    };
+
+    let last_chunk = chunk_at(cam_x + margin).min(tip_chunk);
+    let around_view = chunk_at(cam_x - margin)..=last_chunk;
+
+    // the textures are freed with the sprites
+    view.chunks.retain(|chunk, (entity, _)| {
+        if around_view.contains(chunk) {
+            return true;
+        }
+        commands.entity(*entity).despawn();
+        false
+    });
+
+    for chunk in around_view {
+        let texture = match view.chunks.get(&chunk) {
+            Some(_) if chunk != tip_chunk => continue,
+            Some(_) if !readings_counter.is_changed() => continue,
+            Some((_, texture)) => Some(texture.clone()),
+            None => None,
+        };
+        let data = view.paint_chunk(shown, chunk);
+
+        if let Some(texture) = texture {
+            if let Some(texture) = textures.get_mut(texture) {
+                texture.data = data;
+            }
+            continue;
+        }
+
+        let texture = textures.add(Texture::new(
+            Extent3d::new(
+                READINGS_PER_CHUNK as u32,
+                SPECTROGRAM_ROWS as u32,
+                1,
+            ),
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba8UnormSrgb,
+        ));
+
+        // behind the grid, between the frequencies of interest
+        let width = READINGS_PER_CHUNK as f32 * SINGLE_READING_TO_PX;
+        let bottom = hz_to_y(LOWEST_FREQUENCY_OF_INTEREST);
+        let top = hz_to_y(HIGHEST_FREQUENCY_OF_INTEREST);
+        let x = (chunk as f32 + 0.5) * width * timeline.zoom;
+        let entity = commands
+            .spawn_bundle(SpriteBundle {
+                material: materials.add(texture.clone().into()),
+                sprite: Sprite::new(Vec2::new(
+                    width * timeline.zoom,
+                    top - bottom,
+                )),
+                transform: Transform::from_xyz(x, (top + bottom) / 2.0, -3.0),
+                ..Default::default()
+            })
+            .id();
+        view.chunks.insert(chunk, (entity, texture));
+    }
+}
+
+// Builds the shape of given text drawn with the stroke font, see [`glyphs`].
+fn text_shape(
+    text: &str,
+    height: f32,
+    color: Color,
+    transform: Transform,
+) -> ShapeBundle {
+    let mut path = PathBuilder::new();
+    for polyline in glyphs::polylines(text, height) {
+        let mut points = polyline.into_iter().map(|(x, y)| Vec2::new(x, y));
+        if let Some(first) = points.next() {
+            path.move_to(first);
+        }
+        for point in points {
+            path.line_to(point);
+        }
+    }
+
+    GeometryBuilder::build_as(
+        &path.build(),
+        ShapeColors::new(color),
+        DrawMode::Stroke(
+            StrokeOptions::default()
+                .with_line_width(height / 8.0)
+                .with_line_cap(LineCap::Round)
+                .with_line_join(LineJoin::Round),
+        ),
+        transform,
+    )
+}
+
+// Joins the estimates of a window size in given chunk with straight lines,
+// stretched by the zoom. The line is broken where the window had no estimate
+// yet, and joins the last estimate of the previous chunk.
+fn window_curve_path(
+    estimates: &[Option<f32>],
+    chunk: usize,
+    zoom: f32,
+) -> Path {
+    let start = (chunk * READINGS_PER_CHUNK).saturating_sub(1);
+    let end = (chunk + 1) * READINGS_PER_CHUNK;
+
+    let mut path = PathBuilder::new();
+    let mut drawing = false;
+    for (reading, estimate) in
+        estimates.iter().enumerate().take(end).skip(start)
+    {
+        let hz = match estimate {
+            Some(hz) => *hz,
+            None => {
+                drawing = false;
+                continue;
+            }
+        };
+
+        let x = frequency_readings_count_to_x(reading + 1) * zoom;
+        let point = Vec2::new(x, hz_to_y(hz));
+        if drawing {
+            path.line_to(point);
+        } else {
+            path.move_to(point);
+            drawing = true;
+        }
+    }
+
+    path.build()
+}
+
+// Index of the chunk which the latest reading belongs to.
+fn tip_chunk(readings_count: usize) -> usize {
+    readings_count.saturating_sub(1) / READINGS_PER_CHUNK
+}
+
+// Cuts the quadratic bezier curve at given progress between 0 and 1 with de
+// Casteljau's algorithm. Returns the control point and the end of the first
+// part.
+fn split_quadratic_bezier(
+    from: Vec2,
+    curvature: Vec2,
+    dest: Vec2,
+    progress: f32,
+) -> (Vec2, Vec2) {
+    let a = from.lerp(curvature, progress);
+    let b = curvature.lerp(dest, progress);
+
+    (a, a.lerp(b, progress))
+}
+
 fn frequency_readings_count_to_x(counter: usize) -> f32 {
     // each time we draw a new bit of the curve, we add
     counter as f32 * SINGLE_READING_TO_PX
 }
 
+fn readings_count_to_duration(counter: usize) -> Duration {
+    Duration::from_millis((counter * REPORT_FREQUENCY_AFTER_MS) as u64)
+}
+
+fn duration_to_x(duration: Duration) -> f32 {
+    duration.as_secs_f32() * 1000.0 / REPORT_FREQUENCY_AFTER_MS as f32
+        * SINGLE_READING_TO_PX
+}
+
 fn hz_to_y(hz: f32) -> f32 {
     hz * 100.
 }
 
+// Width of the view in px. The window may have been resized since it was
+// created from the [`WindowDescriptor`].
+fn view_width(windows: &Windows) -> f32 {
+    windows
+        .get_primary()
+        .map(|window| window.width())
+        .unwrap_or_else(|| WindowDescriptor::default().width)
+}
+
+impl FixedToView {
+    // Where on the x axis to place the entity when the camera is at given x.
+    fn x(&self, cam_x: f32, view_width: f32) -> f32 {
+        match self {
+            Self::Left(offset) => cam_x - view_width / 2.0 + offset,
+            Self::Right(offset) => cam_x + view_width / 2.0 + offset,
+        }
+    }
+}
+
+impl WorkoutProgress {
+    fn new(workout: &Workout) -> Self {
+        Self {
+            phases: workout.phases(),
+            history: vec![],
+            current: None,
+            finished: false,
+            label: vec![],
+        }
+    }
+}
+
+impl SpectrogramView {
+    // Paints the columns of given window size in given chunk, see
+    // [`spectrogram::paint`].
+    fn paint_chunk(&self, window: usize, chunk: usize) -> Vec<u8> {
+        let columns = self.columns.get(&window);
+        let readings =
+            chunk * READINGS_PER_CHUNK..(chunk + 1) * READINGS_PER_CHUNK;
+        let columns: Vec<_> = readings
+            .map(|reading| {
+                columns
+                    .and_then(|columns| columns.get(reading))
+                    .and_then(|rows| rows.as_deref())
+            })
+            .collect();
+
+        spectrogram::paint(&columns, SPECTROGRAM_ROWS)
+    }
+}
+
 impl FrequencyReadingsCounter {
     fn as_usize(&self) -> usize {
         self.0
@@ -271,23 +1544,50 @@ impl FrequencyCurveHistory {
             .unwrap_or_else(|| Vec2::new(0., 0.))
     }
 
-    fn build_path(&self) -> Path {
+    // Builds the bits of the curve which given readings added, stretched by
+    // the zoom. The last one is only drawn up to given progress between 0 and
+    // 1.
+    fn build_path(
+        &self,
+        readings: Range<usize>,
+        zoom: f32,
+        progress: f32,
+    ) -> Path {
+        let zoomed = |point: Vec2| Vec2::new(point.x * zoom, point.y);
+        let last = readings.end.saturating_sub(1);
+
         let mut p = PathBuilder::new();
-        for cmd in &self.0 {
-            match cmd {
-                PathCommand::MoveTo(dest) => p.move_to(*dest),
-                PathCommand::QuadraticBezier(curvature, dest) => {
-                    p.quadratic_bezier_to(*curvature, *dest)
-                }
+        for reading in readings {
+            // the curve starts with a move and each reading adds a bezier
+            // curve followed by a move to its end
+            let commands = self.0.get(reading * 2..reading * 2 + 2);
+            let (from, curvature, dest) = match commands {
+                Some(
+                    [PathCommand::MoveTo(from), PathCommand::QuadraticBezier(curvature, dest)],
+                ) => (*from, *curvature, *dest),
+                _ => break,
+            };
+
+            let (curvature, dest) = if reading == last {
+                split_quadratic_bezier(from, curvature, dest, progress)
+            } else {
+                (curvature, dest)
             };
+            p.move_to(zoomed(from));
+            p.quadratic_bezier_to(zoomed(curvature), zoomed(dest));
         }
 
         p.build()
     }
 
-    fn shape(&self) -> ShapeBundle {
+    fn shape(
+        &self,
+        readings: Range<usize>,
+        zoom: f32,
+        progress: f32,
+    ) -> ShapeBundle {
         GeometryBuilder::build_as(
-            &self.build_path(),
+            &self.build_path(readings, zoom, progress),
             ShapeColors::new(Color::BLACK),
             DrawMode::Stroke(StrokeOptions::default().with_line_width(3.0)),
             Transform::default(),
@@ -0,0 +1,293 @@
+//! Guided workouts made of intervals at a target cadence and rests between
+//! them.
+//!
+//! A workout is defined in a TOML file, e.g. 6 × (60 s at 2.5 Hz, 30 s rest)
+//! followed by a minute at 3 Hz:
+//!
+//! ```toml
+//! [[intervals]]
+//! repeat = 6
+//! seconds = 60
+//! target_hz = 2.5
+//! rest_seconds = 30
+//!
+//! [[intervals]]
+//! seconds = 60
+//! target_hz = 3.0
+//! tolerance_hz = 0.2
+//! ```
+
+use crate::app_error::AppResult;
+use jump_rope::{Error, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Workout {
+    pub intervals: Vec<Intervals>,
+}
+
+/// Set of the same intervals, each followed by a rest.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Intervals {
+    #[serde(default = "default_repeat")]
+    pub repeat: usize,
+    pub seconds: f32,
+    pub target_hz: f32,
+    /// How far from the target the cadence can be to count as on target.
+    #[serde(default = "default_tolerance_hz")]
+    pub tolerance_hz: f32,
+    /// There's no rest after the last interval of the workout.
+    #[serde(default)]
+    pub rest_seconds: f32,
+}
+
+/// A part of the workout in which the athlete either jumps or rests.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Phase {
+    /// Time since the start of the workout.
+    pub starts_at: Duration,
+    pub duration: Duration,
+    /// None for rest.
+    pub target: Option<Target>,
+    /// Which interval of the workout this is, or which one follows the rest,
+    /// starting from 1.
+    pub interval: usize,
+    pub intervals_count: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Target {
+    pub hz: f32,
+    pub tolerance_hz: f32,
+}
+
+/// How well the athlete kept the target cadence.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Adherence {
+    /// Share of the time each interval was on target.
+    pub intervals: Vec<f32>,
+    /// Share of the time all intervals were on target.
+    pub overall: f32,
+}
+
+impl Workout {
+    pub fn from_file(path: impl AsRef<Path>) -> AppResult<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(config: &str) -> AppResult<Self> {
+        let workout: Self = toml::from_str(config)?;
+        if workout.intervals.is_empty() {
+            let message = "Workout must have at least one interval";
+            return Err(Error::InvalidArgument(message.to_string()).into());
+        }
+        for set in &workout.intervals {
+            set.validate()?;
+        }
+
+        Ok(workout)
+    }
+
+    /// All phases of the workout in order.
+    pub fn phases(&self) -> Vec<Phase> {
+        let intervals_count: usize =
+            self.intervals.iter().map(|i| i.repeat).sum();
+
+        let mut phases = vec![];
+        let mut starts_at = Duration::default();
+        let mut push = |duration: f32, target, interval| {
+            let duration = Duration::from_secs_f32(duration);
+            phases.push(Phase {
+                starts_at,
+                duration,
+                target,
+                interval,
+                intervals_count,
+            });
+            starts_at += duration;
+        };
+
+        // rest of the previous interval
+        let mut rest_seconds = 0.0;
+        let mut interval = 0;
+        for set in &self.intervals {
+            for _ in 0..set.repeat {
+                interval += 1;
+                if rest_seconds > 0.0 {
+                    push(rest_seconds, None, interval);
+                }
+                rest_seconds = set.rest_seconds;
+
+                let target = Target {
+                    hz: set.target_hz,
+                    tolerance_hz: set.tolerance_hz,
+                };
+                push(set.seconds, Some(target), interval);
+            }
+        }
+
+        phases
+    }
+}
+
+impl Intervals {
+    // Negative or NaN numbers would give phases which end before they start
+    // and targets which cannot be kept.
+    fn validate(&self) -> Result<()> {
+        let positive = [
+            ("seconds", self.seconds),
+            ("target_hz", self.target_hz),
+            ("tolerance_hz", self.tolerance_hz),
+        ];
+        for (name, value) in &positive {
+            if !(value.is_finite() && *value > 0.0) {
+                return Err(Error::InvalidArgument(format!(
+                    "Intervals need positive {}",
+                    name
+                )));
+            }
+        }
+        if !(self.rest_seconds.is_finite() && self.rest_seconds >= 0.0) {
+            return Err(Error::InvalidArgument(
+                "Intervals cannot have negative rest_seconds".to_string(),
+            ));
+        }
+        if self.repeat == 0 {
+            return Err(Error::InvalidArgument(
+                "Intervals must repeat at least once".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Phase {
+    pub fn ends_at(&self) -> Duration {
+        self.starts_at + self.duration
+    }
+
+    pub fn contains(&self, elapsed: Duration) -> bool {
+        self.starts_at <= elapsed && elapsed < self.ends_at()
+    }
+
+    /// E.g. "Interval 2/6 at 150 BPM" or "Rest before interval 3/6".
+    pub fn describe(&self) -> String {
+        match self.target {
+            Some(target) => format!(
+                "Interval {}/{} at {:.0} BPM",
+                self.interval,
+                self.intervals_count,
+                target.hz * 60.0
+            ),
+            None => format!(
+                "Rest before interval {}/{}",
+                self.interval, self.intervals_count
+            ),
+        }
+    }
+}
+
+impl Target {
+    pub fn contains(&self, hz: f32) -> bool {
+        (hz - self.hz).abs() <= self.tolerance_hz
+    }
+}
+
+/// Scores the frequency history, sampled in regular intervals since the start
+/// of the workout, against the targets. Rests aren't scored.
+pub fn adherence(
+    phases: &[Phase],
+    history: &[(Duration, Option<f32>)],
+) -> Adherence {
+    let (mut on_target_total, mut samples_total) = (0, 0);
+
+    let intervals = phases
+        .iter()
+        .filter_map(|phase| Some((phase, phase.target?)))
+        .map(|(phase, target)| {
+            let samples: Vec<_> = history
+                .iter()
+                .filter(|(at, _)| phase.contains(*at))
+                .collect();
+            let on_target = samples
+                .iter()
+                .filter(
+                    |(_, hz)| matches!(hz, Some(hz) if target.contains(*hz)),
+                )
+                .count();
+
+            on_target_total += on_target;
+            samples_total += samples.len();
+            on_target as f32 / samples.len().max(1) as f32
+        })
+        .collect();
+
+    Adherence {
+        intervals,
+        overall: on_target_total as f32 / samples_total.max(1) as f32,
+    }
+}
+
+fn default_repeat() -> usize {
+    1
+}
+
+fn default_tolerance_hz() -> f32 {
+    0.15
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_plans_and_scores_workout() {
+        let workout = Workout::parse(
+            "[[intervals]]\n\
+             repeat = 2\n\
+             seconds = 60\n\
+             target_hz = 2.5\n\
+             rest_seconds = 30\n",
+        )
+        .unwrap();
+
+        let phases = workout.phases();
+        let starts: Vec<_> =
+            phases.iter().map(|p| p.starts_at.as_secs()).collect();
+        assert_eq!(starts, vec![0, 60, 90]);
+        assert_eq!(phases[1].describe(), "Rest before interval 2/2");
+        assert_eq!(phases[2].describe(), "Interval 2/2 at 150 BPM");
+
+        // on target during the first interval, too slow for half of the
+        // second one and not jumping during the rest
+        let history: Vec<_> = (0..150)
+            .map(|second| {
+                let hz = match second {
+                    0..=59 => Some(2.55),
+                    60..=89 => None,
+                    90..=119 => Some(2.0),
+                    _ => Some(2.5),
+                };
+                (Duration::from_secs(second), hz)
+            })
+            .collect();
+
+        let adherence = adherence(&phases, &history);
+        assert_eq!(adherence.intervals, vec![1.0, 0.5]);
+        assert_eq!(adherence.overall, 0.75);
+
+        let invalid = "[[intervals]]\nseconds = 60\ntarget_hz = 2.5\n";
+        assert!(
+            Workout::parse(&format!("{}rest_seconds = -1", invalid)).is_err()
+        );
+        assert!(
+            Workout::parse(&format!("{}tolerance_hz = nan", invalid)).is_err()
+        );
+        assert!(Workout::parse("[[intervals]]\nseconds = 0\ntarget_hz = 2")
+            .is_err());
+    }
+}